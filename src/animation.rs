@@ -0,0 +1,83 @@
+/// How an animation's progress speeds up and slows down over its duration
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Easing {
+    Linear,
+    EaseIn, // Starts slow
+    EaseOut, // Ends slow
+    EaseInOut,
+}
+
+impl Easing {
+    /// Maps linear progress from 0.0 to 1.0 to eased progress
+    pub fn apply(&self, t: f32) -> f32 {
+        let t = t.max(0.0).min(1.0);
+
+        match *self {
+            Easing::Linear => t,
+            Easing::EaseIn => t * t,
+            Easing::EaseOut => t * (2.0 - t),
+            Easing::EaseInOut => if t < 0.5 { 2.0 * t * t } else { -1.0 + (4.0 - 2.0 * t) * t },
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Edge {
+    Left,
+    Right,
+    Top,
+    Bottom,
+}
+
+/// How a window appears when opening, and disappears the same way when closing
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Transition {
+    Expand, // Grows from a line through its middle
+    Fade,
+    Slide(Edge), // Moves in by its own size from that side, fading in
+}
+
+/// Progress to add for `delta_time` seconds of an animation lasting `duration` seconds
+pub fn step(delta_time: f32, duration: f32) -> f32 {
+    if duration > 0.0 {
+        delta_time / duration
+    } else {
+        1.0
+    }
+}
+
+/// A value animated from one number to another over a duration
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct Tween {
+    from: f32,
+    to: f32,
+    duration: f32, // In seconds
+    progress: f32, // Linear, from 0.0 to 1.0
+    easing: Easing,
+}
+
+impl Tween {
+    pub fn new(from: f32, to: f32, duration: f32, easing: Easing) -> Tween {
+        Tween {
+            from: from,
+            to: to,
+            duration: duration,
+            progress: 0.0,
+            easing: easing,
+        }
+    }
+
+    /// Advances the animation by `delta_time` seconds and returns the new value
+    pub fn update(&mut self, delta_time: f32) -> f32 {
+        self.progress = (self.progress + step(delta_time, self.duration)).min(1.0);
+        self.value()
+    }
+
+    pub fn value(&self) -> f32 {
+        self.from + (self.to - self.from) * self.easing.apply(self.progress)
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.progress >= 1.0
+    }
+}
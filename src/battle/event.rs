@@ -0,0 +1,38 @@
+use super::{BattlerRef, ActionKind, BattleResult};
+use database_items::base_item::Parameter;
+use database_items::item::ItemRef;
+use skill_use::Unusable;
+
+/// Something that happened during a turn, in the order it happened
+///
+/// The battle logic is done by the time these are produced, a UI only has to play them back.
+#[derive(PartialEq, Clone, Debug)]
+pub enum BattleEvent {
+    TurnStart(i32),
+    ActionStart { user: BattlerRef, kind: ActionKind },
+    Miss { user: BattlerRef, target: BattlerRef },
+    Evade { user: BattlerRef, target: BattlerRef },
+    Critical { user: BattlerRef, target: BattlerRef },
+    Counter { user: BattlerRef, target: BattlerRef }, // The user counterattacks instead of being hit
+    Reflect { user: BattlerRef, target: BattlerRef }, // The target sends the user's magic back at it
+    Unusable { user: BattlerRef, reason: Unusable }, // The action was skipped
+
+    // Positive amounts are recovery, negative amounts are damage
+    HpChange { target: BattlerRef, amount: i32 },
+    MpChange { target: BattlerRef, amount: i32 },
+    ApChange { target: BattlerRef, amount: i32 },
+
+    StateAdded { target: BattlerRef, state_id: usize },
+    StateRemoved { target: BattlerRef, state_id: usize },
+    EquipmentReleased { target: BattlerRef, item: ItemRef }, // A state change made it unwearable
+    BuffAdded { target: BattlerRef, parameter: Parameter },
+    DebuffAdded { target: BattlerRef, parameter: Parameter },
+    BuffRemoved { target: BattlerRef, parameter: Parameter }, // Buffs and debuffs alike
+    ParameterRaised { target: BattlerRef, parameter: Parameter, amount: i32 },
+    Guard(BattlerRef),
+    Death(BattlerRef),
+    Escape(BattlerRef),
+    Message(String),
+    TurnEnd(i32),
+    BattleEnd(BattleResult),
+}
@@ -0,0 +1,1182 @@
+use std::collections::BTreeMap;
+
+use battler::Battler;
+use database::{Database, ATTACK_SKILL_ID, GUARD_SKILL_ID};
+use database_items::base_item::*;
+use database_items::usable_item::*;
+use database_items::state::{Restriction, AutoRemoval};
+use database_items::item::ItemRef;
+use database_items::enemy::ActionCondition;
+use database_items::troop::{PageCondition, PageSpan};
+use effect;
+use element;
+use equipment::{self, EquipDelta};
+use formula::FormulaContext;
+use game_variables::GameVariables;
+use random::Random;
+use skill_use::{self, Unusable};
+use targeting::Battlefield;
+
+pub use self::event::BattleEvent;
+
+mod event;
+
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
+pub enum Side {
+    Party,
+    Troop,
+}
+
+impl Side {
+    pub fn opposite(&self) -> Side {
+        match *self {
+            Side::Party => Side::Troop,
+            Side::Troop => Side::Party,
+        }
+    }
+}
+
+/// Identifies a battler by its side and its position on that side
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
+pub struct BattlerRef {
+    pub side: Side,
+    pub index: usize,
+}
+
+impl BattlerRef {
+    pub fn new(side: Side, index: usize) -> BattlerRef {
+        BattlerRef {
+            side: side,
+            index: index,
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum ActionKind {
+    Skill(usize),
+    Item(usize),
+}
+
+/// What a battler does on its turn
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct Action {
+    pub kind: ActionKind,
+    pub target: Option<BattlerRef>, // None picks a random target for single target scopes
+}
+
+impl Action {
+    pub fn attack(target: Option<BattlerRef>) -> Action {
+        Action {
+            kind: ActionKind::Skill(ATTACK_SKILL_ID),
+            target: target,
+        }
+    }
+
+    pub fn guard() -> Action {
+        Action {
+            kind: ActionKind::Skill(GUARD_SKILL_ID),
+            target: None,
+        }
+    }
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum BattleResult {
+    Victory,
+    Defeat,
+    Escaped,
+}
+
+/// What the party earns for the enemies it defeated
+#[derive(PartialEq, Debug)]
+pub struct Rewards {
+    pub exp: i32,
+    pub gold: i32,
+    pub items: Vec<ItemRef>,
+}
+
+/// A turn-based battle between the party and a troop of enemies
+///
+/// The battle has no notion of time or graphics: each call to `process_turn` resolves a whole
+/// turn and returns what happened as a list of events.
+pub struct Battle<'a> {
+    database: &'a Database,
+    party: Vec<Battler<'a>>,
+    troop: Vec<Battler<'a>>,
+    actions: Vec<(BattlerRef, Action)>,
+    guarding: Vec<BattlerRef>,
+    escaped: Vec<BattlerRef>,
+    troop_id: Option<usize>,
+    pages_run: Vec<usize>, // Battle span pages that already ran
+    pages_run_this_turn: Vec<usize>, // Turn span pages that already ran
+    turn: i32,
+    result: Option<BattleResult>,
+    released_equipment: EquipDelta, // Taken off party members whose states changed
+    items: BTreeMap<usize, u32>, // Item ids the party has left, with their counts
+    used_items: Vec<usize>, // Consumable items used by the party, once per use
+}
+
+impl<'a> Battle<'a> {
+    pub fn new(database: &'a Database, party: Vec<Battler<'a>>, troop: Vec<Battler<'a>>) -> Battle<'a> {
+        Battle {
+            database: database,
+            party: party,
+            troop: troop,
+            actions: Vec::new(),
+            guarding: Vec::new(),
+            escaped: Vec::new(),
+            troop_id: None,
+            pages_run: Vec::new(),
+            pages_run_this_turn: Vec::new(),
+            turn: 0,
+            result: None,
+            released_equipment: EquipDelta::new(),
+            items: BTreeMap::new(),
+            used_items: Vec::new(),
+        }
+    }
+
+    /// Returns a new Battle against a troop of the database, with its battle event pages
+    pub fn from_troop(database: &'a Database, party: Vec<Battler<'a>>, troop_id: usize) -> Option<Battle<'a>> {
+        let troop = match database.troops.get(&troop_id) {
+            Some(troop) => troop,
+            None => {
+                error!("Illegal troop id {}", troop_id);
+                return None;
+            },
+        };
+
+        // Every member has to make it in, troop pages refer to enemies by their position
+        let mut enemies = Vec::new();
+
+        for member in troop.members.iter() {
+            match Battler::from_enemy(database, member.enemy_id) {
+                Some(enemy) => enemies.push(enemy),
+                None => {
+                    error!("Illegal enemy id {} in troop {}", member.enemy_id, troop_id);
+                    return None;
+                },
+            }
+        }
+
+        let mut battle = Battle::new(database, party, enemies);
+        battle.troop_id = Some(troop_id);
+
+        return Some(battle);
+    }
+
+    /// Number of turns processed so far
+    pub fn turn(&self) -> i32 {
+        self.turn
+    }
+
+    /// How the battle ended, None while it's still going
+    pub fn result(&self) -> Option<BattleResult> {
+        self.result
+    }
+
+    /// Sets the items the party can use, without them the party has no items
+    pub fn set_items(&mut self, items: BTreeMap<usize, u32>) {
+        self.items = items;
+    }
+
+    /// How many of an item the party has left
+    pub fn item_count(&self, item_id: usize) -> u32 {
+        self.items.get(&item_id).cloned().unwrap_or(0)
+    }
+
+    /// Consumable items the party used, to take out of its inventory after the battle
+    pub fn used_items(&self) -> &[usize] {
+        &self.used_items
+    }
+
+    /// Equipment that states made party members take off, it belongs back in the inventory
+    pub fn released_equipment(&self) -> &EquipDelta {
+        &self.released_equipment
+    }
+
+    pub fn battlers(&self, side: Side) -> &[Battler<'a>] {
+        match side {
+            Side::Party => &self.party,
+            Side::Troop => &self.troop,
+        }
+    }
+
+    pub fn battler(&self, battler: BattlerRef) -> &Battler<'a> {
+        &self.battlers(battler.side)[battler.index]
+    }
+
+    fn battler_mut(&mut self, battler: BattlerRef) -> &mut Battler<'a> {
+        match battler.side {
+            Side::Party => &mut self.party[battler.index],
+            Side::Troop => &mut self.troop[battler.index],
+        }
+    }
+
+    /// Every battler of a side that's still fighting
+    pub fn alive(&self, side: Side) -> Vec<BattlerRef> {
+        (0..self.battlers(side).len())
+            .map(|index| BattlerRef::new(side, index))
+            .filter(|&b| self.battler(b).is_alive() && !self.escaped.contains(&b))
+            .collect()
+    }
+
+    /// Sets what a battler will do this turn, battlers without an action attack a random opponent
+    pub fn set_action(&mut self, battler: BattlerRef, action: Action) {
+        self.actions.retain(|&(b, _)| b != battler);
+        self.actions.push((battler, action));
+    }
+
+    /// Ends the battle and returns the party, without buffs and the states that only last for a battle
+    pub fn into_party(mut self) -> Vec<Battler<'a>> {
+        for battler in self.party.iter_mut() {
+            battler.remove_battle_states();
+            battler.clear_buffs();
+        }
+
+        self.party
+    }
+
+    /// Resolves a whole turn and returns what happened
+    pub fn process_turn(&mut self, variables: &GameVariables, random: &mut Random) -> Vec<BattleEvent> {
+        let mut events = Vec::new();
+
+        if self.result.is_some() {
+            return events;
+        }
+
+        self.turn += 1;
+        self.guarding.clear();
+        self.pages_run_this_turn.clear();
+        events.push(BattleEvent::TurnStart(self.turn));
+
+        self.run_pages(false, &mut events);
+
+        for (user, action) in self.action_order(random) {
+            // The user might have been knocked out or put to sleep earlier this turn
+            if !self.battler(user).can_act() || self.escaped.contains(&user) {
+                continue;
+            }
+
+            self.execute_action(user, action, variables, random, &mut events);
+
+            let removed = self.battler_mut(user).update_state_turns(AutoRemoval::ActionEnd);
+            self.states_removed(user, removed, &mut events);
+
+            self.run_pages(false, &mut events);
+
+            self.check_result(&mut events);
+            if self.result.is_some() {
+                break;
+            }
+        }
+
+        if self.result.is_none() {
+            self.end_turn(&mut events);
+            self.check_result(&mut events);
+        }
+
+        self.actions.clear();
+
+        return events;
+    }
+
+    /// Decides every battler's action and sorts them by speed, fastest first
+    fn action_order(&self, random: &mut Random) -> Vec<(BattlerRef, Action)> {
+        let mut order = Vec::new();
+
+        for battler in self.alive(Side::Party).into_iter().chain(self.alive(Side::Troop).into_iter()) {
+            if !self.battler(battler).can_act() {
+                continue;
+            }
+
+            let action = self.decide_action(battler, random);
+
+            // A bit of randomness so equally fast battlers don't always go in the same order
+            let agi = self.battler(battler).param(Parameter::Agi);
+            let speed = agi + random.range(0, agi / 4);
+
+            order.push((speed, battler, action));
+        }
+
+        order.sort_by(|a, b| b.0.cmp(&a.0));
+
+        order.into_iter().map(|(_, battler, action)| (battler, action)).collect()
+    }
+
+    /// Picks a random battler, those with a higher target rate (Tgr) are picked more often
+    fn random_target(&self, candidates: &[BattlerRef], random: &mut Random) -> Option<BattlerRef> {
+        let total = candidates.iter().fold(0.0, |sum, &b| sum + self.battler(b).sparam(SpParameter::Tgr));
+        let mut roll = random.next_f32() * total;
+
+        for &candidate in candidates.iter() {
+            roll -= self.battler(candidate).sparam(SpParameter::Tgr);
+
+            if roll < 0.0 {
+                return Some(candidate);
+            }
+        }
+
+        // Rounding (or everyone having a target rate of 0) can leave the roll unspent
+        candidates.last().cloned()
+    }
+
+    /// Restrictions override the chosen action with an attack on a random target
+    fn decide_action(&self, battler: BattlerRef, random: &mut Random) -> Action {
+        match self.battler(battler).restriction() {
+            Restriction::AttackEnemy => Action::attack(self.random_target(&self.alive(battler.side.opposite()), random)),
+            Restriction::AttackAlly => Action::attack(self.random_target(&self.alive(battler.side), random)),
+            Restriction::AttackAnyone => {
+                let mut candidates = self.alive(Side::Party);
+                candidates.extend(self.alive(Side::Troop));
+                Action::attack(self.random_target(&candidates, random))
+            },
+            _ => match self.actions.iter().find(|&&(b, _)| b == battler) {
+                Some(&(_, action)) => action,
+                None if battler.side == Side::Troop => self.enemy_action(battler, random),
+                None => Action::attack(None),
+            },
+        }
+    }
+
+    /// Picks one of an enemy's actions whose condition holds
+    ///
+    /// Only actions rated within 2 of the best one are considered, and the higher the rating
+    /// the more likely an action is picked.
+    fn enemy_action(&self, battler: BattlerRef, random: &mut Random) -> Action {
+        let enemy = match self.battler(battler).enemy_id().and_then(|id| self.database.enemies.get(&id)) {
+            Some(enemy) => enemy,
+            None => return Action::attack(None),
+        };
+
+        let valid: Vec<_> = enemy.actions.iter()
+            .filter(|a| self.action_condition_met(battler, a.condition))
+            .filter(|a| skill_use::can_use(self.battler(battler), a.skill_id, Occasion::Battle).is_ok())
+            .collect();
+
+        let best = match valid.iter().map(|a| a.rating).max() {
+            Some(best) => best,
+            None => return Action::attack(None),
+        };
+
+        let candidates: Vec<_> = valid.into_iter().filter(|a| a.rating >= best - 2).collect();
+        let total = candidates.iter().fold(0, |sum, a| sum + a.rating - (best - 3));
+        let mut roll = random.below(total as usize) as i32;
+
+        for action in candidates.iter() {
+            roll -= action.rating - (best - 3);
+
+            if roll < 0 {
+                return Action {
+                    kind: ActionKind::Skill(action.skill_id),
+                    target: None,
+                };
+            }
+        }
+
+        Action::attack(None)
+    }
+
+    fn action_condition_met(&self, battler: BattlerRef, condition: ActionCondition) -> bool {
+        let battler = self.battler(battler);
+
+        match condition {
+            ActionCondition::Always => true,
+            ActionCondition::Turn(a, b) => matches_turn(self.turn, a, b),
+            ActionCondition::HpBetween(min, max) => battler.hp_rate() >= min && battler.hp_rate() <= max,
+            ActionCondition::MpBetween(min, max) => battler.mp_rate() >= min && battler.mp_rate() <= max,
+            ActionCondition::State(id) => battler.has_state(id),
+            ActionCondition::PartyLevel(level) => self.party.iter().any(|b| b.level() >= level),
+        }
+    }
+
+    /// Runs the troop's battle event pages whose conditions hold
+    fn run_pages(&mut self, turn_end: bool, events: &mut Vec<BattleEvent>) {
+        let troop = match self.troop_id.and_then(|id| self.database.troops.get(&id)) {
+            Some(troop) => troop,
+            None => return,
+        };
+
+        for (index, page) in troop.pages.iter().enumerate() {
+            let already_run = match page.span {
+                PageSpan::Battle => self.pages_run.contains(&index),
+                PageSpan::Turn => self.pages_run_this_turn.contains(&index),
+                PageSpan::Moment => false,
+            };
+
+            if already_run || !self.page_condition_met(page.condition, turn_end) {
+                continue;
+            }
+
+            match page.span {
+                PageSpan::Battle => self.pages_run.push(index),
+                PageSpan::Turn => self.pages_run_this_turn.push(index),
+                PageSpan::Moment => {},
+            }
+
+            for message in page.messages.iter() {
+                events.push(BattleEvent::Message(message.clone()));
+            }
+        }
+    }
+
+    fn page_condition_met(&self, condition: PageCondition, turn_end: bool) -> bool {
+        match condition {
+            PageCondition::TurnEnd => turn_end,
+            PageCondition::Turn(a, b) => !turn_end && matches_turn(self.turn, a, b),
+            PageCondition::EnemyHp(index, rate) => {
+                self.troop.get(index).map(|b| b.hp_rate() <= rate).unwrap_or(false)
+            },
+            PageCondition::ActorHp(actor_id, rate) => {
+                self.party.iter().any(|b| b.actor_id() == Some(actor_id) && b.hp_rate() <= rate)
+            },
+        }
+    }
+
+    /// EXP, gold and drops for every defeated enemy, escaped enemies don't count
+    pub fn rewards(&self, random: &mut Random) -> Rewards {
+        let mut rewards = Rewards {
+            exp: 0,
+            gold: 0,
+            items: Vec::new(),
+        };
+
+        for (index, battler) in self.troop.iter().enumerate() {
+            if battler.is_alive() || self.escaped.contains(&BattlerRef::new(Side::Troop, index)) {
+                continue;
+            }
+
+            if let Some(enemy) = battler.enemy_id().and_then(|id| self.database.enemies.get(&id)) {
+                rewards.exp += enemy.exp;
+                rewards.gold += enemy.gold;
+
+                for drop in enemy.drops.iter() {
+                    if random.chance(drop.probability) {
+                        rewards.items.push(drop.item);
+                    }
+                }
+            }
+        }
+
+        return rewards;
+    }
+
+    fn usable_item(&self, kind: ActionKind) -> Option<&'a UsableItem> {
+        match kind {
+            ActionKind::Skill(id) => self.database.skills.get(&id).map(|skill| &skill.base),
+            ActionKind::Item(id) => self.database.items.get(&id).map(|item| &item.base),
+        }
+    }
+
+    fn execute_action(&mut self, user: BattlerRef, action: Action, variables: &GameVariables, random: &mut Random, events: &mut Vec<BattleEvent>) {
+        let item = match self.usable_item(action.kind) {
+            Some(item) => item,
+            None => {
+                error!("Illegal action {:?}", action.kind);
+                return;
+            },
+        };
+
+        events.push(BattleEvent::ActionStart { user: user, kind: action.kind });
+
+        if let ActionKind::Skill(skill_id) = action.kind {
+            match skill_use::pay_cost(self.battler_mut(user), skill_id, Occasion::Battle) {
+                Ok(cost) => {
+                    if cost.hp != 0 { events.push(BattleEvent::HpChange { target: user, amount: -cost.hp }); }
+                    if cost.mp != 0 { events.push(BattleEvent::MpChange { target: user, amount: -cost.mp }); }
+                    if cost.ap != 0 { events.push(BattleEvent::ApChange { target: user, amount: -cost.ap }); }
+                },
+                Err(reason) => {
+                    events.push(BattleEvent::Unusable { user: user, reason: reason });
+                    return;
+                },
+            }
+        }
+
+        if let ActionKind::Item(_) = action.kind {
+            if item.occasion != Occasion::Always && item.occasion != Occasion::Battle {
+                events.push(BattleEvent::Unusable { user: user, reason: Unusable::WrongOccasion });
+                return;
+            }
+        }
+
+        if let (ActionKind::Item(item_id), Side::Party) = (action.kind, user.side) {
+            if let Err(reason) = self.use_item(item_id) {
+                events.push(BattleEvent::Unusable { user: user, reason: reason });
+                return;
+            }
+        }
+
+        if action.kind == ActionKind::Skill(GUARD_SKILL_ID) {
+            self.guarding.push(user);
+            events.push(BattleEvent::Guard(user));
+        }
+
+        let normal_attack = action.kind == ActionKind::Skill(ATTACK_SKILL_ID);
+
+        for target in self.targets(user, item, action.target, random) {
+            self.apply_item(user, target, item, normal_attack, variables, random, events);
+        }
+    }
+
+    /// Takes an item the party uses out of its stock, if it's consumable
+    fn use_item(&mut self, item_id: usize) -> Result<(), Unusable> {
+        let count = self.item_count(item_id);
+
+        // Another member may have used the last one earlier this turn
+        if count == 0 {
+            return Err(Unusable::NoItemLeft);
+        }
+
+        let consumable = self.database.items.get(&item_id).map(|item| item.consumable).unwrap_or(false);
+
+        if consumable {
+            self.items.insert(item_id, count - 1);
+            self.used_items.push(item_id);
+        }
+
+        Ok(())
+    }
+
+    /// Turns the scope of an item into the battlers it affects
+    fn targets(&self, user: BattlerRef, item: &UsableItem, chosen: Option<BattlerRef>, random: &mut Random) -> Vec<BattlerRef> {
+        let battlefield = Battlefield {
+            party: &self.party,
+            troop: &self.troop,
+            excluded: &self.escaped,
+        };
+
+        battlefield.resolve(user, item, chosen, |candidates| self.random_target(candidates, random))
+    }
+
+    /// Uses an item on a target, which may counter physical attacks or reflect magical ones
+    fn apply_item(&mut self, user: BattlerRef, target: BattlerRef, item: &UsableItem, normal_attack: bool, variables: &GameVariables, random: &mut Random, events: &mut Vec<BattleEvent>) {
+        if user.side != target.side && self.battler(target).can_act() {
+            if item.hit_type == HitType::PhysicalAttack && random.chance(self.battler(target).xparam(ExParameter::Cnt)) {
+                events.push(BattleEvent::Counter { user: target, target: user });
+
+                if let Some(attack) = self.usable_item(ActionKind::Skill(ATTACK_SKILL_ID)) {
+                    self.invoke_item(target, user, attack, true, variables, random, events);
+                }
+
+                return;
+            }
+
+            if item.hit_type == HitType::MagicalAttack && random.chance(self.battler(target).xparam(ExParameter::Mrf)) {
+                events.push(BattleEvent::Reflect { user: user, target: target });
+                self.invoke_item(user, user, item, normal_attack, variables, random, events);
+                return;
+            }
+        }
+
+        self.invoke_item(user, target, item, normal_attack, variables, random, events);
+    }
+
+    /// Applies an item's damage and effects to a target, after its hit and critical rolls
+    ///
+    /// Normal attacks also inflict the user's AttackState states when they hit.
+    fn invoke_item(&mut self, user: BattlerRef, target: BattlerRef, item: &UsableItem, normal_attack: bool, variables: &GameVariables, random: &mut Random, events: &mut Vec<BattleEvent>) {
+        if item.hit_type == HitType::PhysicalAttack {
+            if !random.chance(self.battler(user).sparam(SpParameter::Hit)) {
+                events.push(BattleEvent::Miss { user: user, target: target });
+                return;
+            }
+
+            if random.chance(self.battler(target).xparam(ExParameter::Eva)) {
+                events.push(BattleEvent::Evade { user: user, target: target });
+                return;
+            }
+        }
+
+        if item.damage.damage_type != DamageType::None {
+            let critical = item.damage.can_crit && random.chance(self.battler(user).xparam(ExParameter::Cri));
+
+            if critical {
+                events.push(BattleEvent::Critical { user: user, target: target });
+            }
+
+            let value = self.damage_value(user, target, item, critical, variables, random);
+            self.apply_damage(user, target, item.damage.damage_type, value, random, events);
+        }
+
+        let mut effects = if normal_attack { attack_state_effects(self.battler(user)) } else { Vec::new() };
+        effects.extend(item.effects.iter().cloned());
+
+        self.apply_effects(target, &effects, random, events);
+    }
+
+    /// Evaluates the damage formula and applies the target's rates to it
+    ///
+    /// Damage (but not recovery) is reduced by the target's Pdr or Mdr for physical or magical
+    /// attacks, and by its Dmr for any kind of hit.
+    fn damage_value(&self, user: BattlerRef, target: BattlerRef, item: &UsableItem, critical: bool, variables: &GameVariables, random: &mut Random) -> i32 {
+        let a = self.battler(user);
+        let b = self.battler(target);
+
+        let mut value = {
+            let mut context = FormulaContext {
+                a: a,
+                b: b,
+                variables: variables,
+                random: random,
+            };
+
+            item.damage.evaluate(&mut context, critical) as f32
+        };
+
+        // A negative rate turns the damage around, the target absorbs it
+        value *= element::damage_rate(a, b, item.damage.element);
+
+        if is_recovery(item.damage.damage_type) {
+            value *= b.sparam(SpParameter::Rec);
+        } else {
+            value *= match item.hit_type {
+                HitType::PhysicalAttack => b.sparam(SpParameter::Pdr),
+                HitType::MagicalAttack => b.sparam(SpParameter::Mdr),
+                HitType::CertainHit => 1.0,
+            };
+            value *= b.sparam(SpParameter::Dmr);
+
+            if self.guarding.contains(&target) {
+                value /= 2.0;
+            }
+        }
+
+        value.round() as i32
+    }
+
+    fn apply_damage(&mut self, user: BattlerRef, target: BattlerRef, damage_type: DamageType, value: i32, random: &mut Random, events: &mut Vec<BattleEvent>) {
+        let hp_change = match damage_type {
+            DamageType::None => 0,
+            DamageType::HpDamage => self.change_hp(target, -value, events),
+            DamageType::MpDamage => { self.change_mp(target, -value, events); 0 },
+            DamageType::ApDamage => { self.change_ap(target, -value, events); 0 },
+            DamageType::HpRecovery => self.change_hp(target, value, events),
+            DamageType::MpRecovery => { self.change_mp(target, value, events); 0 },
+            DamageType::ApRecovery => { self.change_ap(target, value, events); 0 },
+            DamageType::HpDrain => {
+                let taken = self.change_hp(target, -value, events);
+                self.change_hp(user, -taken, events);
+                taken
+            },
+            DamageType::MpDrain => {
+                let taken = self.change_mp(target, -value, events);
+                self.change_mp(user, -taken, events);
+                0
+            },
+            DamageType::ApDrain => {
+                let taken = self.change_ap(target, -value, events);
+                self.change_ap(user, -taken, events);
+                0
+            },
+        };
+
+        // Getting hurt wakes sleeping battlers up
+        if hp_change < 0 && self.battler(target).is_alive() {
+            let removed = self.battler_mut(target).remove_states_by_damage(random);
+            self.states_removed(target, removed, events);
+        }
+    }
+
+    /// Applies an item's effects to a target and reports what changed
+    fn apply_effects(&mut self, target: BattlerRef, effects: &[Effect], random: &mut Random, events: &mut Vec<BattleEvent>) {
+        let (result, died) = {
+            let battler = self.battler_mut(target);
+            let was_alive = battler.is_alive();
+            let result = effect::apply_effects(battler, effects, random);
+
+            (result, was_alive && battler.is_dead())
+        };
+
+        if result.hp != 0 {
+            events.push(BattleEvent::HpChange { target: target, amount: result.hp });
+        }
+
+        if result.mp != 0 {
+            events.push(BattleEvent::MpChange { target: target, amount: result.mp });
+        }
+
+        if result.ap != 0 {
+            events.push(BattleEvent::ApChange { target: target, amount: result.ap });
+        }
+
+        if died {
+            events.push(BattleEvent::Death(target));
+        }
+
+        for &state_id in result.added_states.iter() {
+            events.push(BattleEvent::StateAdded { target: target, state_id: state_id });
+        }
+
+        for &state_id in result.removed_states.iter() {
+            events.push(BattleEvent::StateRemoved { target: target, state_id: state_id });
+        }
+
+        if !result.added_states.is_empty() || !result.removed_states.is_empty() {
+            self.release_unequippable(target, events);
+        }
+
+        for &parameter in result.added_buffs.iter() {
+            events.push(BattleEvent::BuffAdded { target: target, parameter: parameter });
+        }
+
+        for &parameter in result.added_debuffs.iter() {
+            events.push(BattleEvent::DebuffAdded { target: target, parameter: parameter });
+        }
+
+        for &parameter in result.removed_buffs.iter().chain(result.removed_debuffs.iter()) {
+            events.push(BattleEvent::BuffRemoved { target: target, parameter: parameter });
+        }
+
+        for &(parameter, amount) in result.raised_params.iter() {
+            events.push(BattleEvent::ParameterRaised { target: target, parameter: parameter, amount: amount });
+        }
+
+        if result.escaped {
+            self.escape(target, events);
+        }
+    }
+
+    /// Reports states that wore off, and takes off the equipment they were allowing
+    fn states_removed(&mut self, target: BattlerRef, state_ids: Vec<usize>, events: &mut Vec<BattleEvent>) {
+        if state_ids.is_empty() {
+            return;
+        }
+
+        for state_id in state_ids {
+            events.push(BattleEvent::StateRemoved { target: target, state_id: state_id });
+        }
+
+        self.release_unequippable(target, events);
+    }
+
+    /// Takes off the equipment a battler's states no longer allow, enemies have none
+    fn release_unequippable(&mut self, target: BattlerRef, events: &mut Vec<BattleEvent>) {
+        let delta = equipment::release_unequippable(self.battler_mut(target));
+
+        for &item in delta.to_inventory.iter() {
+            events.push(BattleEvent::EquipmentReleased { target: target, item: item });
+        }
+
+        self.released_equipment.append(delta);
+    }
+
+    /// Takes a battler out of the battle, the whole party flees together
+    fn escape(&mut self, battler: BattlerRef, events: &mut Vec<BattleEvent>) {
+        if self.escaped.contains(&battler) {
+            return;
+        }
+
+        events.push(BattleEvent::Escape(battler));
+
+        match battler.side {
+            Side::Party => self.finish(BattleResult::Escaped, events),
+            Side::Troop => self.escaped.push(battler),
+        }
+    }
+
+    /// Changes HP and returns the actual change
+    fn change_hp(&mut self, target: BattlerRef, amount: i32, events: &mut Vec<BattleEvent>) -> i32 {
+        let (change, died) = {
+            let battler = self.battler_mut(target);
+            let was_alive = battler.is_alive();
+            let old_hp = battler.hp();
+
+            battler.set_hp(old_hp + amount);
+
+            (battler.hp() - old_hp, was_alive && battler.is_dead())
+        };
+
+        if change != 0 {
+            events.push(BattleEvent::HpChange { target: target, amount: change });
+        }
+
+        if died {
+            events.push(BattleEvent::Death(target));
+        }
+
+        return change;
+    }
+
+    /// Changes MP and returns the actual change
+    fn change_mp(&mut self, target: BattlerRef, amount: i32, events: &mut Vec<BattleEvent>) -> i32 {
+        let change = {
+            let battler = self.battler_mut(target);
+            let old_mp = battler.mp();
+
+            battler.set_mp(old_mp + amount);
+
+            battler.mp() - old_mp
+        };
+
+        if change != 0 {
+            events.push(BattleEvent::MpChange { target: target, amount: change });
+        }
+
+        return change;
+    }
+
+    /// Changes AP and returns the actual change
+    fn change_ap(&mut self, target: BattlerRef, amount: i32, events: &mut Vec<BattleEvent>) -> i32 {
+        let change = {
+            let battler = self.battler_mut(target);
+            let old_ap = battler.ap();
+
+            battler.set_ap(old_ap + amount);
+
+            battler.ap() - old_ap
+        };
+
+        if change != 0 {
+            events.push(BattleEvent::ApChange { target: target, amount: change });
+        }
+
+        return change;
+    }
+
+    /// Regeneration, slip damage and state countdowns at the end of a turn
+    fn end_turn(&mut self, events: &mut Vec<BattleEvent>) {
+        let mut battlers = self.alive(Side::Party);
+        battlers.extend(self.alive(Side::Troop));
+
+        for battler in battlers {
+            let hp = self.battler_mut(battler).regenerate_hp();
+            if hp != 0 {
+                events.push(BattleEvent::HpChange { target: battler, amount: hp });
+            }
+
+            let mp = self.battler_mut(battler).regenerate_mp();
+            if mp != 0 {
+                events.push(BattleEvent::MpChange { target: battler, amount: mp });
+            }
+
+            let ap = self.battler_mut(battler).regenerate_ap();
+            if ap != 0 {
+                events.push(BattleEvent::ApChange { target: battler, amount: ap });
+            }
+
+            let removed = self.battler_mut(battler).update_state_turns(AutoRemoval::TurnEnd);
+            self.states_removed(battler, removed, events);
+
+            for parameter in self.battler_mut(battler).update_buff_turns() {
+                events.push(BattleEvent::BuffRemoved { target: battler, parameter: parameter });
+            }
+        }
+
+        self.run_pages(true, events);
+
+        events.push(BattleEvent::TurnEnd(self.turn));
+    }
+
+    fn check_result(&mut self, events: &mut Vec<BattleEvent>) {
+        if self.result.is_some() {
+            return;
+        }
+
+        if self.alive(Side::Party).is_empty() {
+            self.finish(BattleResult::Defeat, events);
+        } else if self.alive(Side::Troop).is_empty() {
+            self.finish(BattleResult::Victory, events);
+        }
+    }
+
+    fn finish(&mut self, result: BattleResult, events: &mut Vec<BattleEvent>) {
+        self.result = Some(result);
+        events.push(BattleEvent::BattleEnd(result));
+
+        info!("Battle ended after {} turn(s): {:?}", self.turn, result);
+    }
+}
+
+/// Whether `turn` is turn a, or one of every b turns after it (b = 0 for only turn a)
+fn matches_turn(turn: i32, a: i32, b: i32) -> bool {
+    if b <= 0 {
+        turn == a
+    } else {
+        turn >= a && (turn - a) % b == 0
+    }
+}
+
+/// The states a battler's normal attacks inflict, from AttackState features (e.g. on its weapon)
+fn attack_state_effects(battler: &Battler) -> Vec<Effect> {
+    battler.features().iter()
+        .filter_map(|feature| match **feature {
+            Feature::AttackState(state_id) => Some(Effect::AddState(state_id, 1.0)),
+            _ => None,
+        })
+        .collect()
+}
+
+fn is_recovery(damage_type: DamageType) -> bool {
+    match damage_type {
+        DamageType::HpRecovery | DamageType::MpRecovery | DamageType::ApRecovery => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use database::Database;
+    use random::SequenceRandom;
+
+    // Without AttackState or other noise: the Hornet attacks from the party side, the Slime
+    // defends on the troop side. Normal attacks deal 26 * 4 - 10 * 2 = 84 damage to the Slime.
+    const HORNET: usize = 2;
+    const SLIME: usize = 1;
+    const STARFALL: usize = 3;
+    const POTION: usize = 1;
+
+    fn hornet() -> BattlerRef {
+        BattlerRef::new(Side::Party, 0)
+    }
+
+    fn slime() -> BattlerRef {
+        BattlerRef::new(Side::Troop, 0)
+    }
+
+    /// The demo database with fixed damage, and only the given features on both enemies
+    fn database(hornet_features: Vec<Feature>, slime_features: Vec<Feature>) -> Database {
+        let mut database = Database::load("assets/data").unwrap();
+
+        database.skills.get_mut(&ATTACK_SKILL_ID).unwrap().base.damage.variance = 0.0;
+        database.skills.get_mut(&STARFALL).unwrap().base.damage.variance = 0.0;
+        database.enemies.get_mut(&HORNET).unwrap().base.features = hornet_features;
+        database.enemies.get_mut(&SLIME).unwrap().base.features = slime_features;
+
+        return database;
+    }
+
+    fn new_battle<'a>(database: &'a Database) -> Battle<'a> {
+        let party = vec![Battler::from_enemy(database, HORNET).unwrap()];
+        let troop = vec![Battler::from_enemy(database, SLIME).unwrap()];
+
+        Battle::new(database, party, troop)
+    }
+
+    /// Runs an action and returns its events, `rolls` are the values of every random roll
+    fn act(battle: &mut Battle, user: BattlerRef, action: Action, rolls: Vec<f32>) -> Vec<BattleEvent> {
+        let mut events = Vec::new();
+        battle.execute_action(user, action, &GameVariables::new(), &mut SequenceRandom::new(rolls), &mut events);
+
+        events
+    }
+
+    /// Uses the Starfall skill without paying for it, only the reflection is rolled
+    fn cast_starfall(battle: &mut Battle, reflect_roll: f32) -> Vec<BattleEvent> {
+        let database = battle.database;
+        let starfall = &database.skills[&STARFALL].base;
+        let mut events = Vec::new();
+
+        battle.apply_item(hornet(), slime(), starfall, false, &GameVariables::new(), &mut SequenceRandom::new(vec![reflect_roll]), &mut events);
+
+        events
+    }
+
+    fn attack_start() -> BattleEvent {
+        BattleEvent::ActionStart { user: hornet(), kind: ActionKind::Skill(ATTACK_SKILL_ID) }
+    }
+
+    fn slime_hp(amount: i32) -> BattleEvent {
+        BattleEvent::HpChange { target: slime(), amount: amount }
+    }
+
+    #[test]
+    fn attack_hits() {
+        let database = database(vec![], vec![]);
+        let mut battle = new_battle(&database);
+
+        // Counter, hit, evasion and critical rolls
+        let events = act(&mut battle, hornet(), Action::attack(Some(slime())), vec![0.5, 0.5, 0.5, 0.5]);
+
+        assert_eq!(events, vec![attack_start(), slime_hp(-84)]);
+        assert_eq!(battle.battler(slime()).hp(), 180 - 84);
+    }
+
+    #[test]
+    fn attack_misses_on_a_failed_hit_roll() {
+        let database = database(vec![Feature::SpParameter(SpParameter::Hit, 0.8)], vec![]);
+
+        let mut battle = new_battle(&database);
+        let events = act(&mut battle, hornet(), Action::attack(Some(slime())), vec![0.5, 0.8]);
+        assert_eq!(events, vec![attack_start(), BattleEvent::Miss { user: hornet(), target: slime() }]);
+
+        let mut battle = new_battle(&database);
+        let events = act(&mut battle, hornet(), Action::attack(Some(slime())), vec![0.5, 0.79, 0.5, 0.5]);
+        assert_eq!(events, vec![attack_start(), slime_hp(-84)]);
+    }
+
+    #[test]
+    fn attack_is_evaded() {
+        let database = database(vec![], vec![Feature::ExParameter(ExParameter::Eva, 0.3)]);
+
+        let mut battle = new_battle(&database);
+        let events = act(&mut battle, hornet(), Action::attack(Some(slime())), vec![0.5, 0.5, 0.29]);
+        assert_eq!(events, vec![attack_start(), BattleEvent::Evade { user: hornet(), target: slime() }]);
+        assert_eq!(battle.battler(slime()).hp(), 180);
+
+        let mut battle = new_battle(&database);
+        let events = act(&mut battle, hornet(), Action::attack(Some(slime())), vec![0.5, 0.5, 0.3, 0.5]);
+        assert_eq!(events, vec![attack_start(), slime_hp(-84)]);
+    }
+
+    #[test]
+    fn critical_hit_triples_damage() {
+        let database = database(vec![Feature::ExParameter(ExParameter::Cri, 0.5)], vec![]);
+        let mut battle = new_battle(&database);
+
+        let events = act(&mut battle, hornet(), Action::attack(Some(slime())), vec![0.5, 0.5, 0.5, 0.4]);
+
+        assert_eq!(events, vec![
+            attack_start(),
+            BattleEvent::Critical { user: hornet(), target: slime() },
+            slime_hp(-180), // 252 damage, but only 180 HP to lose
+            BattleEvent::Death(slime()),
+        ]);
+    }
+
+    #[test]
+    fn physical_attack_is_countered() {
+        let database = database(vec![], vec![Feature::ExParameter(ExParameter::Cnt, 0.5)]);
+        let mut battle = new_battle(&database);
+
+        // The counter is a normal attack of the Slime: 22 * 4 - 8 * 2 = 72 damage
+        let events = act(&mut battle, hornet(), Action::attack(Some(slime())), vec![0.4, 0.5, 0.5, 0.5]);
+
+        assert_eq!(events, vec![
+            attack_start(),
+            BattleEvent::Counter { user: slime(), target: hornet() },
+            BattleEvent::HpChange { target: hornet(), amount: -72 },
+        ]);
+        assert_eq!(battle.battler(slime()).hp(), 180);
+    }
+
+    #[test]
+    fn magic_is_reflected() {
+        let database = database(vec![], vec![Feature::ExParameter(ExParameter::Mrf, 0.5)]);
+
+        // Starfall deals 100 + 10 * 2 - 8 * 2 = 104 to the Slime, and 100 to the Hornet itself
+        let mut battle = new_battle(&database);
+        assert_eq!(cast_starfall(&mut battle, 0.5), vec![slime_hp(-104)]);
+
+        let mut battle = new_battle(&database);
+        assert_eq!(cast_starfall(&mut battle, 0.4), vec![
+            BattleEvent::Reflect { user: hornet(), target: slime() },
+            BattleEvent::HpChange { target: hornet(), amount: -100 },
+        ]);
+        assert_eq!(battle.battler(slime()).hp(), 180);
+    }
+
+    #[test]
+    fn physical_damage_rates() {
+        let database = database(vec![], vec![
+            Feature::SpParameter(SpParameter::Pdr, 0.5),
+            Feature::SpParameter(SpParameter::Mdr, 2.0),
+        ]);
+        let mut battle = new_battle(&database);
+
+        let events = act(&mut battle, hornet(), Action::attack(Some(slime())), vec![0.5]);
+        assert_eq!(events, vec![attack_start(), slime_hp(-42)]);
+    }
+
+    #[test]
+    fn magical_damage_rates() {
+        let database = database(vec![], vec![
+            Feature::SpParameter(SpParameter::Pdr, 2.0),
+            Feature::SpParameter(SpParameter::Mdr, 0.5),
+        ]);
+        let mut battle = new_battle(&database);
+
+        assert_eq!(cast_starfall(&mut battle, 0.5), vec![slime_hp(-52)]);
+    }
+
+    #[test]
+    fn damage_rate_applies_to_every_hit_type() {
+        let database = database(vec![], vec![
+            Feature::SpParameter(SpParameter::Pdr, 0.5),
+            Feature::SpParameter(SpParameter::Mdr, 0.5),
+            Feature::SpParameter(SpParameter::Dmr, 0.5),
+        ]);
+
+        let mut battle = new_battle(&database);
+        let events = act(&mut battle, hornet(), Action::attack(Some(slime())), vec![0.5]);
+        assert_eq!(events, vec![attack_start(), slime_hp(-21)]);
+
+        let mut battle = new_battle(&database);
+        assert_eq!(cast_starfall(&mut battle, 0.5), vec![slime_hp(-26)]);
+    }
+
+    #[test]
+    fn items_for_another_occasion_are_unusable() {
+        let mut database = database(vec![], vec![]);
+        database.items.get_mut(&POTION).unwrap().base.occasion = Occasion::Menu;
+
+        let mut battle = new_battle(&database);
+        let mut items = BTreeMap::new();
+        items.insert(POTION, 2);
+        battle.set_items(items);
+
+        let action = Action { kind: ActionKind::Item(POTION), target: Some(hornet()) };
+        let events = act(&mut battle, hornet(), action, vec![0.5]);
+
+        assert_eq!(events, vec![
+            BattleEvent::ActionStart { user: hornet(), kind: ActionKind::Item(POTION) },
+            BattleEvent::Unusable { user: hornet(), reason: Unusable::WrongOccasion },
+        ]);
+        assert_eq!(battle.item_count(POTION), 2);
+        assert!(battle.used_items().is_empty());
+    }
+
+    #[test]
+    fn items_run_out_during_the_turn() {
+        let database = database(vec![], vec![]);
+        let mut battle = new_battle(&database);
+        let mut items = BTreeMap::new();
+        items.insert(POTION, 1);
+        battle.set_items(items);
+
+        let action = Action { kind: ActionKind::Item(POTION), target: Some(hornet()) };
+        let start = BattleEvent::ActionStart { user: hornet(), kind: ActionKind::Item(POTION) };
+
+        assert_eq!(act(&mut battle, hornet(), action, vec![0.5]), vec![start.clone()]);
+        assert_eq!(act(&mut battle, hornet(), action, vec![0.5]), vec![
+            start,
+            BattleEvent::Unusable { user: hornet(), reason: Unusable::NoItemLeft },
+        ]);
+        assert_eq!(battle.item_count(POTION), 0);
+        assert_eq!(battle.used_items(), &[POTION]);
+    }
+
+    #[test]
+    fn target_rate_weighs_random_targets() {
+        let database = database(vec![Feature::SpParameter(SpParameter::Tgr, 3.0)], vec![]);
+        let party = vec![Battler::from_enemy(&database, HORNET).unwrap(), Battler::from_enemy(&database, SLIME).unwrap()];
+        let troop = vec![Battler::from_enemy(&database, SLIME).unwrap()];
+        let battle = Battle::new(&database, party, troop);
+
+        let candidates = vec![hornet(), BattlerRef::new(Side::Party, 1)];
+        let pick = |roll: f32| battle.random_target(&candidates, &mut SequenceRandom::new(vec![roll]));
+
+        // The Hornet takes 3 of the 4 parts of the roll, the Slime the last one
+        assert_eq!(pick(0.0), Some(hornet()));
+        assert_eq!(pick(0.74), Some(hornet()));
+        assert_eq!(pick(0.76), Some(BattlerRef::new(Side::Party, 1)));
+        assert_eq!(pick(0.99), Some(BattlerRef::new(Side::Party, 1)));
+    }
+
+    #[test]
+    fn guarding_halves_damage() {
+        let database = database(vec![], vec![]);
+        let mut battle = new_battle(&database);
+
+        let events = act(&mut battle, slime(), Action::guard(), vec![0.5]);
+        assert_eq!(events, vec![
+            BattleEvent::ActionStart { user: slime(), kind: ActionKind::Skill(GUARD_SKILL_ID) },
+            BattleEvent::Guard(slime()),
+        ]);
+
+        let events = act(&mut battle, hornet(), Action::attack(Some(slime())), vec![0.5]);
+        assert_eq!(events, vec![attack_start(), slime_hp(-42)]);
+    }
+}
@@ -0,0 +1,890 @@
+use std::collections::BTreeMap;
+
+use database::Database;
+use database_items::base_item::*;
+use database_items::state::{Restriction, AutoRemoval};
+use formula::FormulaSubject;
+use random::Random;
+
+/// A state inflicted on a battler
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct ActiveState {
+    pub id: usize,
+    pub turns_left: i32,
+}
+
+/// Buff (positive level) or debuff (negative level) on a parameter
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct ActiveBuff {
+    pub level: i32,
+    pub turns_left: i32,
+}
+
+/// What changed after gaining EXP
+#[derive(PartialEq, Debug)]
+pub struct LevelUpResult {
+    pub old_level: i32,
+    pub new_level: i32,
+    pub learned_skills: Vec<usize>,
+}
+
+/// A participant in battle (or a party member in menus)
+///
+/// Stats are never stored pre-computed, every query aggregates the features of all sources
+/// (actor, class, equipment and states) so there's a single source of truth:
+/// * `param` is `(base + growth + equipment bonuses) * product of Parameter rates * buff rate`
+/// * `xparam` is the sum of ExParameter values (they're chances, e.g. 0.05 = 5% evasion)
+/// * `sparam` and `element_rate` are products of their rates, starting from 1.0
+pub struct Battler<'a> {
+    database: &'a Database,
+    pub name: String,
+    actor_id: Option<usize>,
+    enemy_id: Option<usize>,
+    class_id: Option<usize>,
+    level: i32,
+    max_level: i32,
+    exp: i32,
+    skills: Vec<usize>,
+    hp: i32,
+    mp: i32,
+    ap: i32,
+    base_params: BTreeMap<Parameter, i32>,
+    param_growth: BTreeMap<Parameter, i32>, // Permanent, from RaiseParameter effects
+    equipment: BTreeMap<EquipSlot, usize>,
+    states: Vec<ActiveState>, // Sorted by priority, highest first
+    buffs: BTreeMap<Parameter, ActiveBuff>,
+}
+
+impl<'a> Battler<'a> {
+    /// Returns a new Battler with the given base parameters, at full HP/MP/AP
+    pub fn new(database: &'a Database, name: &str, base_params: BTreeMap<Parameter, i32>) -> Battler<'a> {
+        let mut battler = Battler {
+            database: database,
+            name: name.to_string(),
+            actor_id: None,
+            enemy_id: None,
+            class_id: None,
+            level: 1,
+            max_level: 1,
+            exp: 0,
+            skills: Vec::new(),
+            hp: 0,
+            mp: 0,
+            ap: 0,
+            base_params: base_params,
+            param_growth: BTreeMap::new(),
+            equipment: BTreeMap::new(),
+            states: Vec::new(),
+            buffs: BTreeMap::new(),
+        };
+
+        battler.recover_all();
+
+        return battler;
+    }
+
+    /// Returns a new Battler for an actor of the database, with its class and initial equipment
+    pub fn from_actor(database: &'a Database, actor_id: usize) -> Option<Battler<'a>> {
+        let actor = match database.actors.get(&actor_id) {
+            Some(actor) => actor,
+            None => {
+                error!("Illegal actor id {}", actor_id);
+                return None;
+            },
+        };
+
+        // Actors take their base parameters from their class instead
+        let mut battler = Battler::new(database, &actor.base.name, BTreeMap::new());
+        battler.actor_id = Some(actor_id);
+        battler.class_id = Some(actor.class);
+        battler.level = actor.initial_level;
+        battler.max_level = actor.max_level;
+        battler.equipment = actor.equipment.clone();
+
+        if let Some(class) = database.classes.get(&actor.class) {
+            battler.exp = class.exp_for_level(actor.initial_level);
+            battler.skills = class.skills_learned_between(0, actor.initial_level);
+        }
+
+        battler.recover_all();
+
+        return Some(battler);
+    }
+
+    /// Returns a new Battler for an enemy of the database
+    pub fn from_enemy(database: &'a Database, enemy_id: usize) -> Option<Battler<'a>> {
+        let enemy = match database.enemies.get(&enemy_id) {
+            Some(enemy) => enemy,
+            None => {
+                error!("Illegal enemy id {}", enemy_id);
+                return None;
+            },
+        };
+
+        let mut battler = Battler::new(database, &enemy.base.name, enemy.parameters.clone());
+        battler.enemy_id = Some(enemy_id);
+        battler.recover_all();
+
+        return Some(battler);
+    }
+
+    pub fn actor_id(&self) -> Option<usize> {
+        self.actor_id
+    }
+
+    pub fn enemy_id(&self) -> Option<usize> {
+        self.enemy_id
+    }
+
+    pub fn database(&self) -> &'a Database {
+        self.database
+    }
+
+    /// Collects the features of every source affecting this battler
+    pub fn features(&self) -> Vec<&'a Feature> {
+        let database = self.database;
+        let mut features = Vec::new();
+
+        if let Some(actor) = self.actor_id.and_then(|id| database.actors.get(&id)) {
+            features.extend(actor.base.features.iter());
+        }
+
+        if let Some(enemy) = self.enemy_id.and_then(|id| database.enemies.get(&id)) {
+            features.extend(enemy.base.features.iter());
+        }
+
+        if let Some(class) = self.class_id.and_then(|id| database.classes.get(&id)) {
+            features.extend(class.base.features.iter());
+        }
+
+        for (slot, id) in self.equipment.iter() {
+            if let Some(item) = database.equip_item(*slot, *id) {
+                features.extend(item.base.features.iter());
+            }
+        }
+
+        for active in self.states.iter() {
+            if let Some(state) = database.states.get(&active.id) {
+                features.extend(state.base.features.iter());
+            }
+        }
+
+        return features;
+    }
+
+    /// Base value of a parameter from the class curve, before equipment and features
+    pub fn param_base(&self, parameter: Parameter) -> i32 {
+        match self.class_id.and_then(|id| self.database.classes.get(&id)) {
+            Some(class) => class.param(parameter, self.level),
+            None => self.base_params.get(&parameter).cloned().unwrap_or(0),
+        }
+    }
+
+    /// Sum of the parameter bonuses of all equipped items
+    pub fn param_equip_bonus(&self, parameter: Parameter) -> i32 {
+        self.equipment.iter()
+            .filter_map(|(slot, id)| self.database.equip_item(*slot, *id))
+            .filter_map(|item| item.parameters.get(&parameter))
+            .fold(0, |sum, value| sum + value)
+    }
+
+    /// Product of the Parameter features for a parameter
+    pub fn param_rate(&self, parameter: Parameter) -> f32 {
+        self.features().iter().fold(1.0, |rate, feature| match **feature {
+            Feature::Parameter(p, value) if p == parameter => rate * value,
+            _ => rate,
+        })
+    }
+
+    /// Permanent growth of a parameter
+    pub fn param_growth(&self, parameter: Parameter) -> i32 {
+        self.param_growth.get(&parameter).cloned().unwrap_or(0)
+    }
+
+    /// Permanently raises (or with a negative amount, lowers) a parameter
+    pub fn raise_param(&mut self, parameter: Parameter, amount: i32) {
+        *self.param_growth.entry(parameter).or_insert(0) += amount;
+        self.refresh();
+    }
+
+    /// Rate a parameter is multiplied by because of its buffs or debuffs, from the system settings
+    pub fn buff_rate(&self, parameter: Parameter) -> f32 {
+        let system = &self.database.system;
+        let level = self.buff_level(parameter);
+
+        if level > 0 {
+            system.buff_rates[level as usize - 1]
+        } else if level < 0 {
+            system.debuff_rates[-level as usize - 1]
+        } else {
+            1.0
+        }
+    }
+
+    /// Effective value of a parameter
+    pub fn param(&self, parameter: Parameter) -> i32 {
+        let base = self.param_base(parameter) + self.param_growth(parameter) + self.param_equip_bonus(parameter);
+        let value = base as f32 * self.param_rate(parameter) * self.buff_rate(parameter);
+
+        // A battler with no max HP would be dead on arrival
+        let min = if parameter == Parameter::Mhp { 1 } else { 0 };
+
+        (value.floor() as i32).max(min)
+    }
+
+    /// Effective value of an ex-parameter, as a chance between 0.0 and 1.0 (or more)
+    pub fn xparam(&self, parameter: ExParameter) -> f32 {
+        self.features().iter().fold(0.0, |sum, feature| match **feature {
+            Feature::ExParameter(p, value) if p == parameter => sum + value,
+            _ => sum,
+        })
+    }
+
+    /// Effective value of a sp-parameter, as a rate where 1.0 is normal
+    pub fn sparam(&self, parameter: SpParameter) -> f32 {
+        self.features().iter().fold(1.0, |rate, feature| match **feature {
+            Feature::SpParameter(p, value) if p == parameter => rate * value,
+            _ => rate,
+        })
+    }
+
+    /// Rate damage of the given element is multiplied by
+    pub fn element_rate(&self, element: Element) -> f32 {
+        self.features().iter().fold(1.0, |rate, feature| match **feature {
+            Feature::ElementRate(e, value) if e == element => rate * value,
+            _ => rate,
+        })
+    }
+
+    /// Rate debuffs land with
+    pub fn debuff_rate(&self) -> f32 {
+        self.features().iter().fold(1.0, |rate, feature| match **feature {
+            Feature::DebuffRate(value) => rate * value,
+            _ => rate,
+        })
+    }
+
+    /// Rate states land with
+    pub fn state_rate(&self) -> f32 {
+        self.features().iter().fold(1.0, |rate, feature| match **feature {
+            Feature::StateRate(value) => rate * value,
+            _ => rate,
+        })
+    }
+
+    pub fn hp(&self) -> i32 {
+        self.hp
+    }
+
+    pub fn mp(&self) -> i32 {
+        self.mp
+    }
+
+    pub fn ap(&self) -> i32 {
+        self.ap
+    }
+
+    pub fn mhp(&self) -> i32 {
+        self.param(Parameter::Mhp)
+    }
+
+    pub fn mmp(&self) -> i32 {
+        self.param(Parameter::Mmp)
+    }
+
+    pub fn map(&self) -> i32 {
+        self.param(Parameter::Map)
+    }
+
+    /// Current HP as a fraction of max HP
+    pub fn hp_rate(&self) -> f32 {
+        self.hp as f32 / self.mhp() as f32
+    }
+
+    /// Current MP as a fraction of max MP, 0 for battlers without MP
+    pub fn mp_rate(&self) -> f32 {
+        let mmp = self.mmp();
+        if mmp > 0 { self.mp as f32 / mmp as f32 } else { 0.0 }
+    }
+
+    pub fn set_hp(&mut self, hp: i32) {
+        self.hp = clamp(hp, 0, self.mhp());
+
+        // The dead don't stay poisoned
+        if self.hp == 0 {
+            self.states.clear();
+            self.buffs.clear();
+        }
+    }
+
+    pub fn set_mp(&mut self, mp: i32) {
+        self.mp = clamp(mp, 0, self.mmp());
+    }
+
+    pub fn set_ap(&mut self, ap: i32) {
+        self.ap = clamp(ap, 0, self.map());
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.hp == 0
+    }
+
+    pub fn is_alive(&self) -> bool {
+        !self.is_dead()
+    }
+
+    /// Restores HP, MP and AP to their maximum
+    pub fn recover_all(&mut self) {
+        self.hp = self.mhp();
+        self.mp = self.mmp();
+        self.ap = self.map();
+    }
+
+    /// Clamps HP, MP and AP after something changed their maximum (e.g. equipment)
+    pub fn refresh(&mut self) {
+        let (hp, mp, ap) = (self.hp, self.mp, self.ap);
+        self.set_hp(hp);
+        self.set_mp(mp);
+        self.set_ap(ap);
+    }
+
+    pub fn level(&self) -> i32 {
+        self.level
+    }
+
+    pub fn exp(&self) -> i32 {
+        self.exp
+    }
+
+    /// Total EXP needed for the next level, None at max level or without a class
+    pub fn next_level_exp(&self) -> Option<i32> {
+        if self.level >= self.max_level {
+            return None;
+        }
+
+        self.class_id.and_then(|id| self.database.classes.get(&id)).map(|class| class.exp_for_level(self.level + 1))
+    }
+
+    /// Adds EXP and raises the level as many times as it allows, up to the max level
+    pub fn gain_exp(&mut self, amount: i32) -> LevelUpResult {
+        let old_level = self.level;
+        let mut learned_skills = Vec::new();
+
+        self.exp = self.exp.saturating_add(amount.max(0));
+
+        while let Some(needed) = self.next_level_exp() {
+            if self.exp < needed {
+                break;
+            }
+
+            self.level += 1;
+        }
+
+        // Don't let EXP pile up beyond what the max level needs
+        if self.level >= self.max_level {
+            if let Some(class) = self.class_id.and_then(|id| self.database.classes.get(&id)) {
+                self.exp = self.exp.min(class.exp_for_level(self.max_level));
+            }
+        }
+
+        if self.level > old_level {
+            if let Some(class) = self.class_id.and_then(|id| self.database.classes.get(&id)) {
+                for skill_id in class.skills_learned_between(old_level, self.level) {
+                    if self.learn_skill(skill_id) {
+                        learned_skills.push(skill_id);
+                    }
+                }
+            }
+
+            self.refresh();
+            info!("{} reached level {}", self.name, self.level);
+        }
+
+        LevelUpResult {
+            old_level: old_level,
+            new_level: self.level,
+            learned_skills: learned_skills,
+        }
+    }
+
+    /// Switches to another class at the same level, learning its skills up to that level
+    ///
+    /// EXP is set to what the level takes in the new class. Returns false if the class doesn't
+    /// exist. Call `equipment::release_unequippable` afterwards, the new class may not allow
+    /// everything that's equipped.
+    pub fn change_class(&mut self, class_id: usize) -> bool {
+        let class = match self.database.classes.get(&class_id) {
+            Some(class) => class,
+            None => {
+                error!("Illegal class id {}", class_id);
+                return false;
+            },
+        };
+
+        self.class_id = Some(class_id);
+        self.exp = class.exp_for_level(self.level);
+
+        for skill_id in class.skills_learned_between(0, self.level) {
+            self.learn_skill(skill_id);
+        }
+
+        self.refresh();
+
+        return true;
+    }
+
+    /// Skills learned through the class, in the order they were learned
+    pub fn skills(&self) -> &[usize] {
+        &self.skills
+    }
+
+    /// Learns a skill, returning false if it was already known
+    pub fn learn_skill(&mut self, skill_id: usize) -> bool {
+        if self.skills.contains(&skill_id) {
+            return false;
+        }
+
+        self.skills.push(skill_id);
+
+        return true;
+    }
+
+    pub fn forget_skill(&mut self, skill_id: usize) {
+        self.skills.retain(|&id| id != skill_id);
+    }
+
+    pub fn equipment(&self) -> &BTreeMap<EquipSlot, usize> {
+        &self.equipment
+    }
+
+    /// Puts an item in a slot (or empties it) without any checks, returning what was there
+    ///
+    /// Use `equipment::change_equip` for changes made by the player.
+    pub fn set_equipment(&mut self, slot: EquipSlot, id: Option<usize>) -> Option<usize> {
+        let previous = match id {
+            Some(id) => self.equipment.insert(slot, id),
+            None => self.equipment.remove(&slot),
+        };
+
+        self.refresh();
+
+        return previous;
+    }
+
+    pub fn states(&self) -> &[ActiveState] {
+        &self.states
+    }
+
+    pub fn has_state(&self, id: usize) -> bool {
+        self.states.iter().any(|s| s.id == id)
+    }
+
+    /// Whether a StateResist feature makes this battler immune to a state
+    pub fn is_state_resisted(&self, id: usize) -> bool {
+        self.features().iter().any(|feature| **feature == Feature::StateResist(id))
+    }
+
+    /// Inflicts a state, or restarts its duration if it's already inflicted
+    ///
+    /// Returns false if the state doesn't exist, is resisted, or the battler is dead.
+    pub fn add_state(&mut self, id: usize, random: &mut Random) -> bool {
+        let state = match self.database.states.get(&id) {
+            Some(state) => state,
+            None => {
+                error!("Illegal state id {}", id);
+                return false;
+            },
+        };
+
+        if self.is_dead() || self.is_state_resisted(id) {
+            return false;
+        }
+
+        let turns = random.range(state.min_turns, state.max_turns);
+
+        if let Some(active) = self.states.iter_mut().find(|s| s.id == id) {
+            active.turns_left = turns;
+            return true;
+        }
+
+        self.states.push(ActiveState {
+            id: id,
+            turns_left: turns,
+        });
+        self.sort_states();
+        self.refresh();
+
+        return true;
+    }
+
+    /// Removes a state, returning whether it was inflicted
+    pub fn remove_state(&mut self, id: usize) -> bool {
+        let count = self.states.len();
+        self.states.retain(|s| s.id != id);
+
+        if self.states.len() == count {
+            return false;
+        }
+
+        self.refresh();
+
+        return true;
+    }
+
+    fn sort_states(&mut self) {
+        let database = self.database;
+        let priority = |id: usize| database.states.get(&id).map(|s| s.priority).unwrap_or(0);
+
+        self.states.sort_by(|a, b| priority(b.id).cmp(&priority(a.id)));
+    }
+
+    /// Removes every state matching `predicate`, returning the removed ids
+    fn remove_states_where<F>(&mut self, mut predicate: F) -> Vec<usize> where F: FnMut(&ActiveState) -> bool {
+        let removed: Vec<usize> = self.states.iter().filter(|s| predicate(s)).map(|s| s.id).collect();
+
+        if !removed.is_empty() {
+            self.states.retain(|s| !removed.contains(&s.id));
+            self.refresh();
+        }
+
+        return removed;
+    }
+
+    /// Counts down the states that expire at the given timing and removes the finished ones
+    ///
+    /// Returns the ids of the removed states.
+    pub fn update_state_turns(&mut self, timing: AutoRemoval) -> Vec<usize> {
+        let database = self.database;
+
+        for active in self.states.iter_mut() {
+            if database.states.get(&active.id).map(|s| s.auto_removal) == Some(timing) {
+                active.turns_left -= 1;
+            }
+        }
+
+        self.remove_states_where(|s| {
+            s.turns_left <= 0 && database.states.get(&s.id).map(|state| state.auto_removal) == Some(timing)
+        })
+    }
+
+    /// Rolls for the states that wear off when taking damage, returning the removed ids
+    pub fn remove_states_by_damage(&mut self, random: &mut Random) -> Vec<usize> {
+        let database = self.database;
+
+        self.remove_states_where(|s| {
+            let chance = database.states.get(&s.id).map(|state| state.remove_by_damage).unwrap_or(0.0);
+            chance > 0.0 && random.chance(chance)
+        })
+    }
+
+    /// Removes the states that don't outlast a battle, returning the removed ids
+    pub fn remove_battle_states(&mut self) -> Vec<usize> {
+        let database = self.database;
+
+        self.remove_states_where(|s| database.states.get(&s.id).map(|state| state.remove_at_battle_end).unwrap_or(false))
+    }
+
+    pub fn buffs(&self) -> &BTreeMap<Parameter, ActiveBuff> {
+        &self.buffs
+    }
+
+    /// Buff level of a parameter, negative for debuffs
+    pub fn buff_level(&self, parameter: Parameter) -> i32 {
+        self.buffs.get(&parameter).map(|b| b.level).unwrap_or(0)
+    }
+
+    /// Raises the buff level of a parameter by one (cancelling a debuff level) and restarts its duration
+    ///
+    /// Returns false if the battler is dead or the buff already stacks as high as the system allows.
+    pub fn add_buff(&mut self, parameter: Parameter, turns: i32) -> bool {
+        self.change_buff_level(parameter, 1, turns)
+    }
+
+    /// Lowers the buff level of a parameter by one (cancelling a buff level) and restarts its duration
+    pub fn add_debuff(&mut self, parameter: Parameter, turns: i32) -> bool {
+        self.change_buff_level(parameter, -1, turns)
+    }
+
+    fn change_buff_level(&mut self, parameter: Parameter, change: i32, turns: i32) -> bool {
+        let system = &self.database.system;
+        let level = self.buff_level(parameter) + change;
+
+        if self.is_dead() || level > system.buff_rates.len() as i32 || -level > system.debuff_rates.len() as i32 {
+            return false;
+        }
+
+        if level == 0 {
+            self.buffs.remove(&parameter);
+        } else {
+            self.buffs.insert(parameter, ActiveBuff {
+                level: level,
+                turns_left: turns,
+            });
+        }
+
+        self.refresh();
+
+        return true;
+    }
+
+    /// Removes every buff level of a parameter, returning whether it was buffed
+    pub fn remove_buff(&mut self, parameter: Parameter) -> bool {
+        if self.buff_level(parameter) <= 0 {
+            return false;
+        }
+
+        self.buffs.remove(&parameter);
+        self.refresh();
+
+        return true;
+    }
+
+    /// Counts down buff and debuff durations at the end of a turn and removes the finished ones
+    ///
+    /// Returns the parameters that went back to normal.
+    pub fn update_buff_turns(&mut self) -> Vec<Parameter> {
+        for buff in self.buffs.values_mut() {
+            buff.turns_left -= 1;
+        }
+
+        let expired: Vec<Parameter> = self.buffs.iter().filter(|&(_, b)| b.turns_left <= 0).map(|(&p, _)| p).collect();
+
+        if !expired.is_empty() {
+            for parameter in expired.iter() {
+                self.buffs.remove(parameter);
+            }
+
+            self.refresh();
+        }
+
+        return expired;
+    }
+
+    /// Removes all buffs and debuffs, they never outlast a battle
+    pub fn clear_buffs(&mut self) {
+        self.buffs.clear();
+        self.refresh();
+    }
+
+    /// Removes every debuff level of a parameter, returning whether it was debuffed
+    pub fn remove_debuff(&mut self, parameter: Parameter) -> bool {
+        if self.buff_level(parameter) >= 0 {
+            return false;
+        }
+
+        self.buffs.remove(&parameter);
+        self.refresh();
+
+        return true;
+    }
+
+    /// The most severe restriction among inflicted states
+    pub fn restriction(&self) -> Restriction {
+        self.states.iter()
+            .filter_map(|s| self.database.states.get(&s.id))
+            .map(|state| state.restriction)
+            .fold(Restriction::None, |worst, restriction| if restriction > worst { restriction } else { worst })
+    }
+
+    /// Whether the battler can take an action this turn
+    pub fn can_act(&self) -> bool {
+        self.is_alive() && self.restriction() != Restriction::CannotAct
+    }
+
+    /// Applies HP regeneration and slip damage at the end of a turn, returning the HP change
+    ///
+    /// Slip damage never kills, it leaves the battler at 1 HP at worst.
+    pub fn regenerate_hp(&mut self) -> i32 {
+        if self.is_dead() {
+            return 0;
+        }
+
+        let mhp = self.mhp();
+        let slip_damage = self.states.iter()
+            .filter_map(|s| self.database.states.get(&s.id))
+            .fold(0.0, |sum, state| sum + state.slip_damage);
+
+        let mut change = self.param(Parameter::Hrg) - (mhp as f32 * slip_damage).floor() as i32;
+        if self.hp + change < 1 {
+            change = 1 - self.hp;
+        }
+
+        let old_hp = self.hp;
+        self.set_hp(old_hp + change);
+
+        self.hp - old_hp
+    }
+
+    /// Applies MP regeneration at the end of a turn, returning the MP change
+    pub fn regenerate_mp(&mut self) -> i32 {
+        if self.is_dead() {
+            return 0;
+        }
+
+        let old_mp = self.mp;
+        let change = self.param(Parameter::Mrg);
+        self.set_mp(old_mp + change);
+
+        self.mp - old_mp
+    }
+
+    /// Applies AP regeneration at the end of a turn, returning the AP change
+    pub fn regenerate_ap(&mut self) -> i32 {
+        if self.is_dead() {
+            return 0;
+        }
+
+        let old_ap = self.ap;
+        let change = self.param(Parameter::Arg);
+        self.set_ap(old_ap + change);
+
+        self.ap - old_ap
+    }
+}
+
+impl<'a> FormulaSubject for Battler<'a> {
+    fn param(&self, parameter: Parameter) -> i32 {
+        Battler::param(self, parameter)
+    }
+
+    fn xparam(&self, parameter: ExParameter) -> f32 {
+        Battler::xparam(self, parameter)
+    }
+
+    fn sparam(&self, parameter: SpParameter) -> f32 {
+        Battler::sparam(self, parameter)
+    }
+
+    fn hp(&self) -> i32 {
+        self.hp
+    }
+
+    fn mp(&self) -> i32 {
+        self.mp
+    }
+
+    fn ap(&self) -> i32 {
+        self.ap
+    }
+}
+
+fn clamp(value: i32, min: i32, max: i32) -> i32 {
+    if value < min {
+        min
+    } else if value > max {
+        max
+    } else {
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use battle::{Battle, BattleEvent, BattlerRef, Action, Side};
+    use game_variables::GameVariables;
+    use random::SequenceRandom;
+
+    /// The demo database without the Slime's features, it has 22 Atk and 10 Def
+    fn database() -> Database {
+        let mut database = Database::load("assets/data").unwrap();
+        database.enemies.get_mut(&1).unwrap().base.features = Vec::new();
+
+        return database;
+    }
+
+    fn slime<'a>(database: &'a Database) -> Battler<'a> {
+        Battler::from_enemy(database, 1).unwrap()
+    }
+
+    #[test]
+    fn buffs_stack_up_to_the_system_limit() {
+        let database = database();
+        let mut slime = slime(&database);
+
+        // The demo system has two buff rates, 1.25 and 1.5
+        assert!(slime.add_buff(Parameter::Atk, 3));
+        assert_eq!(slime.param(Parameter::Atk), 27);
+        assert!(slime.add_buff(Parameter::Atk, 3));
+        assert_eq!(slime.param(Parameter::Atk), 33);
+        assert!(!slime.add_buff(Parameter::Atk, 3));
+        assert_eq!(slime.buff_level(Parameter::Atk), 2);
+
+        // Debuffs take buff levels away first
+        assert!(slime.add_debuff(Parameter::Atk, 3));
+        assert_eq!(slime.buff_level(Parameter::Atk), 1);
+        assert!(slime.add_debuff(Parameter::Atk, 3));
+        assert!(slime.buffs().is_empty());
+    }
+
+    #[test]
+    fn debuffs_stack_up_to_the_system_limit() {
+        let database = database();
+        let mut slime = slime(&database);
+
+        // The demo system has two debuff rates, 0.75 and 0.5
+        assert!(slime.add_debuff(Parameter::Atk, 3));
+        assert_eq!(slime.param(Parameter::Atk), 16);
+        assert!(slime.add_debuff(Parameter::Atk, 3));
+        assert_eq!(slime.param(Parameter::Atk), 11);
+        assert!(!slime.add_debuff(Parameter::Atk, 3));
+        assert_eq!(slime.buff_level(Parameter::Atk), -2);
+    }
+
+    #[test]
+    fn stack_limit_follows_the_number_of_rates() {
+        let mut database = database();
+        database.system.buff_rates = vec![1.5, 2.0, 3.0];
+        database.system.debuff_rates = Vec::new();
+        let mut slime = slime(&database);
+
+        for _ in 0..3 {
+            assert!(slime.add_buff(Parameter::Def, 3));
+        }
+        assert!(!slime.add_buff(Parameter::Def, 3));
+        assert_eq!(slime.param(Parameter::Def), 30);
+
+        assert!(!slime.add_debuff(Parameter::Atk, 3));
+        assert_eq!(slime.buff_level(Parameter::Atk), 0);
+    }
+
+    #[test]
+    fn buffs_expire_after_their_turns() {
+        let database = database();
+        let mut slime = slime(&database);
+        slime.add_buff(Parameter::Atk, 2);
+        slime.add_debuff(Parameter::Def, 1);
+
+        assert_eq!(slime.update_buff_turns(), vec![Parameter::Def]);
+        assert_eq!(slime.param(Parameter::Def), 10);
+        assert_eq!(slime.buff_level(Parameter::Atk), 1);
+
+        // Stacking restarts the duration
+        slime.add_buff(Parameter::Atk, 2);
+        assert_eq!(slime.update_buff_turns(), vec![]);
+        assert_eq!(slime.update_buff_turns(), vec![Parameter::Atk]);
+        assert_eq!(slime.param(Parameter::Atk), 22);
+    }
+
+    #[test]
+    fn buffs_expire_at_turn_end() {
+        let database = database();
+        let hornet = Battler::from_enemy(&database, 2).unwrap();
+        let mut slime = slime(&database);
+        slime.add_buff(Parameter::Mdf, 2);
+
+        let mut battle = Battle::new(&database, vec![hornet], vec![slime]);
+        let slime = BattlerRef::new(Side::Troop, 0);
+        let expired = BattleEvent::BuffRemoved { target: slime, parameter: Parameter::Mdf };
+
+        battle.set_action(BattlerRef::new(Side::Party, 0), Action::guard());
+        let events = battle.process_turn(&GameVariables::new(), &mut SequenceRandom::new(vec![0.5]));
+        assert!(!events.contains(&expired));
+        assert_eq!(battle.battler(slime).buff_level(Parameter::Mdf), 1);
+
+        battle.set_action(BattlerRef::new(Side::Party, 0), Action::guard());
+        let events = battle.process_turn(&GameVariables::new(), &mut SequenceRandom::new(vec![0.5]));
+        assert!(events.contains(&expired));
+        assert_eq!(battle.battler(slime).buff_level(Parameter::Mdf), 0);
+    }
+}
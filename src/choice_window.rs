@@ -0,0 +1,181 @@
+use sfml::graphics::{Transformable, Text, Drawable, Color, RenderTarget, RenderStates};
+
+use window::{WindowState, Window, CONTENT_PADDING};
+use selectable_window::SelectableWindow;
+use game_settings_manager::GameSettingsManager;
+
+/// An option the player can pick
+pub struct Choice {
+    pub text: String,
+    pub enabled: bool, // Disabled choices are shown faded and can't be picked
+}
+
+impl Choice {
+    pub fn new(text: &str) -> Choice {
+        Choice {
+            text: text.to_string(),
+            enabled: true,
+        }
+    }
+
+    pub fn disabled(text: &str) -> Choice {
+        Choice {
+            text: text.to_string(),
+            enabled: false,
+        }
+    }
+}
+
+/// What happens when the player cancels a choice
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum CancelBehavior {
+    Disallow, // Nothing, a choice has to be made
+    Choose(usize), // Picks that choice, even if it's disabled
+    Cancel, // Ends with ChoiceResult::Cancelled
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum ChoiceResult {
+    Chosen(usize),
+    Cancelled,
+}
+
+/// Space between the lines of choices
+const LINE_SPACING: f32 = 6.0;
+
+// A window listing choices for the player to pick one, e.g. to answer a message
+// The caller moves the selection and confirms or cancels, then reads the outcome with `result`.
+pub struct ChoiceWindow<'a> {
+    list: SelectableWindow<'a>,
+    enabled: Vec<bool>, // For each choice
+    cancel_behavior: CancelBehavior,
+    result: Option<ChoiceResult>,
+}
+
+impl<'a> ChoiceWindow<'a> {
+    /// Returns a new ChoiceWindow at (x, y), sized to fit the choices, with `default` selected
+    pub fn new(settings: &'a GameSettingsManager, choices: Vec<Choice>, default: usize, cancel_behavior: CancelBehavior, x: f32, y: f32) -> ChoiceWindow<'a> {
+        let mut text = Text::new_init("", &settings.font, settings.default_font_size).unwrap();
+        let line_height = settings.default_font_size as f32 + LINE_SPACING;
+
+        let mut width: f32 = 0.0;
+        for choice in &choices {
+            text.set_string(&choice.text);
+            width = width.max(text.get_local_bounds().width);
+        }
+
+        let padding = CONTENT_PADDING as f32 * 2.0;
+        let mut list = SelectableWindow::new(&settings.window_skin, x, y, width + padding * 2.0, line_height * choices.len() as f32 + padding, 1, line_height);
+
+        let enabled = choices.iter().map(|choice| choice.enabled).collect();
+        let choice_count = choices.len();
+        let text_top = LINE_SPACING / 2.0;
+
+        // Disabled choices are drawn at half opacity
+        list.set_items(choice_count, Box::new(move |index, contents, rect| {
+            let choice = &choices[index];
+
+            text.set_string(&choice.text);
+            text.set_color(&Color::new_rgba(255, 255, 255, if choice.enabled { 255 } else { 128 }));
+            text.set_position2f(rect.left + CONTENT_PADDING as f32, rect.top + text_top);
+            contents.draw(&text);
+        }));
+
+        if default < choice_count {
+            list.set_index(default);
+        } else {
+            error!("Illegal default choice {}, defaulting to 0", default);
+        }
+
+        let cancel_behavior = match cancel_behavior {
+            CancelBehavior::Choose(index) if index >= choice_count => {
+                error!("Illegal cancel choice {}, cancelling is disallowed", index);
+                CancelBehavior::Disallow
+            },
+            cancel_behavior => cancel_behavior,
+        };
+
+        ChoiceWindow {
+            list: list,
+            enabled: enabled,
+            cancel_behavior: cancel_behavior,
+            result: None,
+        }
+    }
+
+    /// The choice the player made, once they made it
+    pub fn result(&self) -> Option<ChoiceResult> {
+        self.result
+    }
+
+    pub fn selected(&self) -> usize {
+        self.list.get_index()
+    }
+
+    /// The window under the choices, e.g. to change its transition
+    pub fn window(&mut self) -> &mut Window<'a> {
+        self.list.window()
+    }
+
+    pub fn update(&mut self, delta_time: f32) {
+        self.list.update(delta_time);
+    }
+
+    /// Input is only taken while the window is open and no choice was made yet
+    fn is_active(&mut self) -> bool {
+        self.result.is_none() && self.list.item_count() > 0 && self.list.window().is_open()
+    }
+
+    pub fn move_down(&mut self) {
+        if self.is_active() {
+            self.list.move_down();
+        }
+    }
+
+    pub fn move_up(&mut self) {
+        if self.is_active() {
+            self.list.move_up();
+        }
+    }
+
+    /// Picks the selected choice, unless it's disabled
+    pub fn confirm(&mut self) {
+        if !self.is_active() {
+            return;
+        }
+
+        let index = self.list.get_index();
+
+        if self.enabled[index] {
+            self.finish(ChoiceResult::Chosen(index));
+        } else {
+            info!("Choice {} is disabled", index);
+        }
+    }
+
+    pub fn cancel(&mut self) {
+        if !self.is_active() {
+            return;
+        }
+
+        match self.cancel_behavior {
+            CancelBehavior::Disallow => { },
+            CancelBehavior::Choose(index) => self.finish(ChoiceResult::Chosen(index)),
+            CancelBehavior::Cancel => self.finish(ChoiceResult::Cancelled),
+        }
+    }
+
+    fn finish(&mut self, result: ChoiceResult) {
+        info!("Choice made: {:?}", result);
+
+        self.result = Some(result);
+        self.list.set_active(false);
+        self.list.set_state(WindowState::Closing);
+    }
+}
+
+impl<'a> Drawable for ChoiceWindow<'a> {
+    fn draw<RT: RenderTarget>(&self, target: &mut RT, rs: &mut RenderStates) {
+        target.draw_with_renderstates(&self.list, rs);
+    }
+}
@@ -0,0 +1,205 @@
+use std::fmt::Display;
+use std::fs::File;
+use std::io::Read;
+use std::str::FromStr;
+
+use super::DatabaseError;
+
+/// A single "key = value" line of a data file
+pub struct Entry {
+    pub key: String,
+    pub value: String,
+    pub line: usize,
+}
+
+/// A block of entries introduced by an "[id]" header
+///
+/// Data files look like this:
+///
+/// ```text
+/// # Comments start with a hash
+/// [1]
+/// name = Potion
+/// effect = RecoverHp 0.0 50
+/// effect = RemoveState 2 1.0
+/// ```
+///
+/// Keys that describe lists (features, effects, ...) may be repeated.
+pub struct Record {
+    pub id: usize,
+    pub line: usize,
+    file: String,
+    entries: Vec<Entry>,
+}
+
+impl Record {
+    /// Returns an error pointing at the given line of this record's file
+    pub fn error<T: Display>(&self, line: usize, message: T) -> DatabaseError {
+        DatabaseError::new(&self.file, line, message)
+    }
+
+    /// Returns the entry for a key that must appear exactly once
+    pub fn get(&self, key: &str) -> Result<&Entry, DatabaseError> {
+        let mut matches = self.entries.iter().filter(|e| e.key == key);
+
+        match (matches.next(), matches.next()) {
+            (Some(entry), None) => Ok(entry),
+            (Some(_), Some(duplicate)) => Err(self.error(duplicate.line, format!("Duplicate key '{}' in record [{}]", key, self.id))),
+            (None, _) => Err(self.error(self.line, format!("Missing key '{}' in record [{}]", key, self.id))),
+        }
+    }
+
+    /// Returns the raw value of a required key
+    pub fn string(&self, key: &str) -> Result<String, DatabaseError> {
+        self.get(key).map(|entry| entry.value.clone())
+    }
+
+    /// Returns the raw value of an optional key, falling back to `default` when it's absent
+    pub fn string_or(&self, key: &str, default: &str) -> Result<String, DatabaseError> {
+        if self.has(key) {
+            self.string(key)
+        } else {
+            Ok(default.to_string())
+        }
+    }
+
+    /// Parses the value of a required key
+    pub fn parse<T>(&self, key: &str) -> Result<T, DatabaseError> where T: FromStr, T::Err: Display {
+        let entry = try!(self.get(key));
+        self.parse_entry(entry)
+    }
+
+    /// Parses the value of an optional key, falling back to `default` when it's absent
+    pub fn parse_or<T>(&self, key: &str, default: T) -> Result<T, DatabaseError> where T: FromStr, T::Err: Display {
+        if self.has(key) {
+            self.parse(key)
+        } else {
+            Ok(default)
+        }
+    }
+
+    /// Parses the value of an optional key
+    pub fn parse_opt<T>(&self, key: &str) -> Result<Option<T>, DatabaseError> where T: FromStr, T::Err: Display {
+        if self.has(key) {
+            self.parse(key).map(Some)
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Parses every value of a repeatable key, in the order they appear
+    pub fn parse_all<T>(&self, key: &str) -> Result<Vec<T>, DatabaseError> where T: FromStr, T::Err: Display {
+        let mut values = Vec::new();
+
+        for entry in self.entries.iter().filter(|e| e.key == key) {
+            values.push(try!(self.parse_entry(entry)));
+        }
+
+        Ok(values)
+    }
+
+    /// Returns every entry of a repeatable key
+    pub fn all(&self, key: &str) -> Vec<&Entry> {
+        self.entries.iter().filter(|e| e.key == key).collect()
+    }
+
+    /// Returns every entry in the order they appear, for keys that apply to the entry before them
+    pub fn entries(&self) -> &[Entry] {
+        &self.entries
+    }
+
+    pub fn has(&self, key: &str) -> bool {
+        self.entries.iter().any(|e| e.key == key)
+    }
+
+    /// Parses the value of a specific entry
+    pub fn parse_entry<T>(&self, entry: &Entry) -> Result<T, DatabaseError> where T: FromStr, T::Err: Display {
+        entry.value.parse().map_err(|e| self.error(entry.line, format!("Invalid value '{}' for key '{}': {}", entry.value, entry.key, e)))
+    }
+
+    /// Fails on keys the loader doesn't know about, which are most likely typos
+    pub fn check_keys(&self, known: &[&str]) -> Result<(), DatabaseError> {
+        for entry in self.entries.iter() {
+            if !known.contains(&&entry.key[..]) {
+                return Err(self.error(entry.line, format!("Unknown key '{}' in record [{}]", entry.key, self.id)));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads and splits a data file into records
+pub fn load(path: &str) -> Result<Vec<Record>, DatabaseError> {
+    let mut contents = String::new();
+
+    match File::open(path).and_then(|mut file| file.read_to_string(&mut contents)) {
+        Ok(_) => parse(path, &contents),
+        Err(e) => Err(DatabaseError::new(path, 0, format!("Failed to read file: {}", e))),
+    }
+}
+
+/// Splits the contents of a data file into records
+pub fn parse(file: &str, contents: &str) -> Result<Vec<Record>, DatabaseError> {
+    let mut records: Vec<Record> = Vec::new();
+
+    for (index, raw_line) in contents.lines().enumerate() {
+        let line_number = index + 1;
+        let line = raw_line.trim();
+
+        // Skip blank lines and comments
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        // Record header
+        if line.starts_with('[') {
+            if !line.ends_with(']') {
+                return Err(DatabaseError::new(file, line_number, "Unterminated record header, expected ']'"));
+            }
+
+            let id_string = line[1..line.len() - 1].trim();
+            let id = match usize::from_str(id_string) {
+                Ok(id) => id,
+                Err(_) => return Err(DatabaseError::new(file, line_number, format!("Invalid record id '{}'", id_string))),
+            };
+
+            if let Some(previous) = records.iter().find(|r| r.id == id) {
+                return Err(DatabaseError::new(file, line_number, format!("Duplicate record id {} (first defined on line {})", id, previous.line)));
+            }
+
+            records.push(Record {
+                id: id,
+                line: line_number,
+                file: file.to_string(),
+                entries: Vec::new(),
+            });
+
+            continue;
+        }
+
+        // Key/value pair
+        let separator = match line.find('=') {
+            Some(position) => position,
+            None => return Err(DatabaseError::new(file, line_number, "Expected 'key = value' or '[id]'")),
+        };
+
+        let key = line[..separator].trim();
+        let value = line[separator + 1..].trim();
+
+        if key.is_empty() {
+            return Err(DatabaseError::new(file, line_number, "Missing key before '='"));
+        }
+
+        match records.last_mut() {
+            Some(record) => record.entries.push(Entry {
+                key: key.to_string(),
+                value: value.to_string(),
+                line: line_number,
+            }),
+            None => return Err(DatabaseError::new(file, line_number, "Entry appears before the first '[id]' header")),
+        }
+    }
+
+    return Ok(records);
+}
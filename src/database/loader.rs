@@ -0,0 +1,406 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::str::FromStr;
+
+use database_items::split_variant;
+use database_items::base_item::*;
+use database_items::usable_item::*;
+use database_items::equip_item::{EquipItem, EquipKind};
+use database_items::item::{Item, ItemType};
+use database_items::skill::{Skill, Cost};
+use database_items::actor::Actor;
+use database_items::state::{State, Restriction, AutoRemoval};
+use database_items::enemy::{Enemy, Drop};
+use database_items::troop::{Troop, TroopPage};
+use database_items::system::{System, ElementRateMode};
+use database_items::class::{Class, Curve, ExpCurve, Learning, MAX_LEVEL};
+use formula::Formula;
+
+use super::DatabaseError;
+use super::data_file::{self, Record, Entry};
+
+const BASE_KEYS: &'static [&'static str] = &["name", "icon", "description", "feature"];
+const USABLE_KEYS: &'static [&'static str] = &["scope", "target_side", "for_dead", "occasion", "hit_type", "damage", "element", "formula", "variance", "critical", "effect"];
+
+/// Loads a data file and converts each of its records with `convert`
+pub fn load_table<T, F>(path: &str, convert: F) -> Result<BTreeMap<usize, T>, DatabaseError> where F: Fn(&Record) -> Result<T, DatabaseError> {
+    let mut table = BTreeMap::new();
+
+    for record in try!(data_file::load(path)).iter() {
+        table.insert(record.id, try!(convert(record)));
+    }
+
+    return Ok(table);
+}
+
+/// Loads a data file that must contain exactly one record, e.g. the system settings
+pub fn load_single<T, F>(path: &str, convert: F) -> Result<T, DatabaseError> where F: Fn(&Record) -> Result<T, DatabaseError> {
+    let records = try!(data_file::load(path));
+
+    match records.len() {
+        1 => convert(&records[0]),
+        count => Err(DatabaseError::new(path, 0, format!("Expected exactly one record, found {}", count))),
+    }
+}
+
+/// Fails on any key that isn't part of one of the given key sets
+fn check_keys(record: &Record, key_sets: &[&[&str]]) -> Result<(), DatabaseError> {
+    let mut known = Vec::new();
+
+    for keys in key_sets.iter() {
+        known.extend(keys.iter().cloned());
+    }
+
+    record.check_keys(&known)
+}
+
+/// Parses an entry of the form "First Second", e.g. "Atk 10" or "RightHand 1"
+fn parse_pair<A, B>(record: &Record, entry: &Entry) -> Result<(A, B), DatabaseError>
+    where A: FromStr, A::Err: Display, B: FromStr, B::Err: Display
+{
+    let result = split_variant(&entry.value).and_then(|(first, rest)| {
+        if rest.len() != 1 {
+            return Err(format!("Expected two values, found {}", rest.len() + 1));
+        }
+
+        let first = try!(first.parse::<A>().map_err(|e| format!("'{}': {}", first, e)));
+        let second = try!(rest[0].parse::<B>().map_err(|e| format!("'{}': {}", rest[0], e)));
+
+        Ok((first, second))
+    });
+
+    result.map_err(|message| record.error(entry.line, format!("Invalid value '{}' for key '{}': {}", entry.value, entry.key, message)))
+}
+
+/// Parses the repeatable "parameter = Atk 10" key
+fn parameters(record: &Record) -> Result<BTreeMap<Parameter, i32>, DatabaseError> {
+    let mut parameters = BTreeMap::new();
+
+    for entry in record.all("parameter") {
+        let (parameter, value) = try!(parse_pair::<Parameter, i32>(record, entry));
+
+        if parameters.insert(parameter, value).is_some() {
+            return Err(record.error(entry.line, format!("Parameter {:?} is set twice", parameter)));
+        }
+    }
+
+    return Ok(parameters);
+}
+
+fn base_item(record: &Record) -> Result<BaseItem, DatabaseError> {
+    Ok(BaseItem {
+        id: record.id,
+        name: try!(record.string("name")),
+        icon_index: try!(record.parse_or("icon", 0)),
+        description: try!(record.string_or("description", "")),
+        features: try!(record.parse_all("feature")),
+    })
+}
+
+fn usable_item(record: &Record) -> Result<UsableItem, DatabaseError> {
+    let source = try!(record.string_or("formula", "0"));
+    let formula = match Formula::compile(&source) {
+        Ok(formula) => formula,
+        Err(e) => {
+            let line = record.get("formula").map(|entry| entry.line).unwrap_or(record.line);
+            return Err(record.error(line, format!("Invalid damage formula in record [{}]: {}", record.id, e)));
+        },
+    };
+
+    Ok(UsableItem {
+        base: try!(base_item(record)),
+        scope: try!(record.parse_or("scope", Scope::Noone)),
+        target_side: try!(record.parse_or("target_side", TargetSide::Opponents)),
+        for_dead: try!(record.parse_or("for_dead", false)),
+        occasion: try!(record.parse_or("occasion", Occasion::Always)),
+        hit_type: try!(record.parse_or("hit_type", HitType::CertainHit)),
+        damage: Damage {
+            damage_type: try!(record.parse_or("damage", DamageType::None)),
+            element: try!(record.parse_or("element", DamageElement::None)),
+            formula: formula,
+            variance: try!(record.parse_or("variance", 0.0)),
+            can_crit: try!(record.parse_or("critical", false)),
+        },
+        effects: try!(record.parse_all("effect")),
+    })
+}
+
+pub fn item(record: &Record) -> Result<Item, DatabaseError> {
+    try!(check_keys(record, &[BASE_KEYS, USABLE_KEYS, &["item_type", "price", "consumable"]]));
+
+    Ok(Item {
+        base: try!(usable_item(record)),
+        item_type: try!(record.parse_or("item_type", ItemType::Regular)),
+        price: try!(record.parse_or("price", 0)),
+        consumable: try!(record.parse_or("consumable", true)),
+    })
+}
+
+pub fn skill(record: &Record) -> Result<Skill, DatabaseError> {
+    try!(check_keys(record, &[BASE_KEYS, USABLE_KEYS, &["skill_type", "hp_cost", "mp_cost", "ap_cost", "required_weapon"]]));
+
+    Ok(Skill {
+        base: try!(usable_item(record)),
+        skill_type: try!(record.parse_or("skill_type", SkillType::Common)),
+        hp_cost: try!(record.parse_or("hp_cost", Cost::None)),
+        mp_cost: try!(record.parse_or("mp_cost", Cost::None)),
+        ap_cost: try!(record.parse_or("ap_cost", Cost::None)),
+        required_weapon: try!(record.parse_opt("required_weapon")),
+    })
+}
+
+pub fn weapon(record: &Record) -> Result<EquipItem, DatabaseError> {
+    try!(check_keys(record, &[BASE_KEYS, &["weapon_type", "two_handed", "price", "parameter"]]));
+
+    Ok(EquipItem {
+        base: try!(base_item(record)),
+        kind: EquipKind::Weapon(try!(record.parse("weapon_type"))),
+        slot: EquipSlot::RightHand,
+        two_handed: try!(record.parse_or("two_handed", false)),
+        price: try!(record.parse_or("price", 0)),
+        parameters: try!(parameters(record)),
+    })
+}
+
+pub fn armor(record: &Record) -> Result<EquipItem, DatabaseError> {
+    try!(check_keys(record, &[BASE_KEYS, &["armor_type", "slot", "price", "parameter"]]));
+
+    let slot = try!(record.parse("slot"));
+    if slot == EquipSlot::LeftHand || slot == EquipSlot::RightHand {
+        return Err(record.error(try!(record.get("slot")).line, "Armors can't go in hand slots"));
+    }
+
+    Ok(EquipItem {
+        base: try!(base_item(record)),
+        kind: EquipKind::Armor(try!(record.parse("armor_type"))),
+        slot: slot,
+        two_handed: false,
+        price: try!(record.parse_or("price", 0)),
+        parameters: try!(parameters(record)),
+    })
+}
+
+pub fn actor(record: &Record) -> Result<Actor, DatabaseError> {
+    try!(check_keys(record, &[BASE_KEYS, &["class", "initial_level", "max_level", "character_sprite", "face_sprite", "face_index", "equip"]]));
+
+    let mut equipment = BTreeMap::new();
+
+    for entry in record.all("equip") {
+        let (slot, id) = try!(parse_pair::<EquipSlot, usize>(record, entry));
+
+        if equipment.insert(slot, id).is_some() {
+            return Err(record.error(entry.line, format!("Slot {:?} is equipped twice", slot)));
+        }
+    }
+
+    let initial_level = try!(record.parse_or("initial_level", 1));
+    let max_level = try!(record.parse_or("max_level", MAX_LEVEL));
+
+    if max_level < 1 || max_level > MAX_LEVEL {
+        return Err(record.error(record.line, format!("max_level {} must be between 1 and {}", max_level, MAX_LEVEL)));
+    }
+
+    if initial_level < 1 || initial_level > max_level {
+        return Err(record.error(record.line, format!("initial_level {} must be between 1 and max_level {}", initial_level, max_level)));
+    }
+
+    Ok(Actor {
+        base: try!(base_item(record)),
+        class: try!(record.parse("class")),
+        initial_level: initial_level,
+        max_level: max_level,
+        character_sprite_filename: try!(record.string_or("character_sprite", "")),
+        face_sprite_filename: try!(record.string_or("face_sprite", "")),
+        face_index: try!(record.parse_or("face_index", 0)),
+        equipment: equipment,
+    })
+}
+
+pub fn state(record: &Record) -> Result<State, DatabaseError> {
+    try!(check_keys(record, &[BASE_KEYS, &["restriction", "priority", "auto_removal", "min_turns", "max_turns",
+                                          "remove_at_battle_end", "remove_by_damage", "slip_damage",
+                                          "message_added", "message_persisting", "message_removed"]]));
+
+    let min_turns = try!(record.parse_or("min_turns", 1));
+    let max_turns = try!(record.parse_or("max_turns", min_turns));
+
+    if min_turns < 1 || max_turns < min_turns {
+        return Err(record.error(record.line, format!("Invalid turn range {}-{} in record [{}]", min_turns, max_turns, record.id)));
+    }
+
+    Ok(State {
+        base: try!(base_item(record)),
+        restriction: try!(record.parse_or("restriction", Restriction::None)),
+        priority: try!(record.parse_or("priority", 50)),
+        auto_removal: try!(record.parse_or("auto_removal", AutoRemoval::None)),
+        min_turns: min_turns,
+        max_turns: max_turns,
+        remove_at_battle_end: try!(record.parse_or("remove_at_battle_end", false)),
+        remove_by_damage: try!(record.parse_or("remove_by_damage", 0.0)),
+        slip_damage: try!(record.parse_or("slip_damage", 0.0)),
+        message_added: try!(record.string_or("message_added", "")),
+        message_persisting: try!(record.string_or("message_persisting", "")),
+        message_removed: try!(record.string_or("message_removed", "")),
+    })
+}
+
+pub fn class(record: &Record) -> Result<Class, DatabaseError> {
+    try!(check_keys(record, &[BASE_KEYS, &["parameter", "exp_curve", "learning"]]));
+
+    // Parameters are written as "parameter = Mhp 450 40" and expanded to a value per level
+    let mut parameters = BTreeMap::new();
+
+    for entry in record.all("parameter") {
+        let (parameter, curve) = match split_variant(&entry.value) {
+            Ok((name, _)) => {
+                let parameter = try!(name.parse::<Parameter>().map_err(|e| record.error(entry.line, e)));
+                let curve = try!(entry.value[name.len()..].parse::<Curve>().map_err(|e| record.error(entry.line, e)));
+                (parameter, curve)
+            },
+            Err(e) => return Err(record.error(entry.line, e)),
+        };
+
+        let values = (1..MAX_LEVEL + 1).map(|level| curve.value(level)).collect();
+
+        if parameters.insert(parameter, values).is_some() {
+            return Err(record.error(entry.line, format!("Parameter {:?} is set twice", parameter)));
+        }
+    }
+
+    let mut learnings = Vec::new();
+
+    for entry in record.all("learning") {
+        let (level, skill_id) = try!(parse_pair::<i32, usize>(record, entry));
+
+        if level < 1 || level > MAX_LEVEL {
+            return Err(record.error(entry.line, format!("Learning level {} is outside 1-{}", level, MAX_LEVEL)));
+        }
+
+        learnings.push(Learning {
+            level: level,
+            skill_id: skill_id,
+        });
+    }
+
+    Ok(Class {
+        base: try!(base_item(record)),
+        parameters: parameters,
+        exp_curve: try!(record.parse_or("exp_curve", ExpCurve { base: 30.0, extra: 20.0, exponent: 2.0 })),
+        learnings: learnings,
+    })
+}
+
+pub fn enemy(record: &Record) -> Result<Enemy, DatabaseError> {
+    try!(check_keys(record, &[BASE_KEYS, &["battler_sprite", "parameter", "exp", "gold", "drop", "action"]]));
+
+    let mut drops = Vec::new();
+
+    for entry in record.all("drop") {
+        let drop: Drop = try!(record.parse_entry(entry));
+
+        if drop.probability < 0.0 || drop.probability > 1.0 {
+            return Err(record.error(entry.line, "Drop probability must be between 0.0 and 1.0"));
+        }
+
+        drops.push(drop);
+    }
+
+    Ok(Enemy {
+        base: try!(base_item(record)),
+        battler_sprite_filename: try!(record.string_or("battler_sprite", "")),
+        parameters: try!(parameters(record)),
+        exp: try!(record.parse_or("exp", 0)),
+        gold: try!(record.parse_or("gold", 0)),
+        drops: drops,
+        actions: try!(record.parse_all("action")),
+    })
+}
+
+pub fn troop(record: &Record) -> Result<Troop, DatabaseError> {
+    try!(record.check_keys(&["name", "member", "page", "message"]));
+
+    // Messages belong to the page declared before them
+    let mut pages: Vec<TroopPage> = Vec::new();
+
+    for entry in record.entries() {
+        match &entry.key[..] {
+            "page" => {
+                let (span, condition) = match split_variant(&entry.value) {
+                    Ok((span, _)) => (
+                        try!(span.parse().map_err(|e| record.error(entry.line, e))),
+                        try!(entry.value[span.len()..].trim().parse().map_err(|e| record.error(entry.line, e))),
+                    ),
+                    Err(e) => return Err(record.error(entry.line, e)),
+                };
+
+                pages.push(TroopPage {
+                    condition: condition,
+                    span: span,
+                    messages: Vec::new(),
+                });
+            },
+
+            "message" => match pages.last_mut() {
+                Some(page) => page.messages.push(entry.value.clone()),
+                None => return Err(record.error(entry.line, "Message appears before the first page")),
+            },
+
+            _ => {},
+        }
+    }
+
+    let members = try!(record.parse_all("member"));
+    if members.is_empty() {
+        return Err(record.error(record.line, format!("Troop [{}] has no members", record.id)));
+    }
+
+    Ok(Troop {
+        id: record.id,
+        name: try!(record.string("name")),
+        members: members,
+        pages: pages,
+    })
+}
+
+pub fn system(record: &Record) -> Result<System, DatabaseError> {
+    try!(record.check_keys(&["currency_unit", "max_gold", "max_stack", "buff_rate", "debuff_rate", "element_rate_mode"]));
+
+    let mut debuff_rates = Vec::new();
+
+    for entry in record.all("debuff_rate") {
+        let rate: f32 = try!(record.parse_entry(entry));
+
+        if rate < 0.0 {
+            return Err(record.error(entry.line, "Debuff rates can't be negative"));
+        }
+
+        debuff_rates.push(rate);
+    }
+
+    Ok(System {
+        currency_unit: try!(record.string_or("currency_unit", "G")),
+        max_gold: try!(record.parse_or("max_gold", 9999999)),
+        max_stack: try!(record.parse_or("max_stack", 99)),
+        buff_rates: try!(record.parse_all("buff_rate")),
+        debuff_rates: debuff_rates,
+        element_rate_mode: try!(record.parse_or("element_rate_mode", ElementRateMode::Max)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item_error(contents: &str) -> String {
+        let records = data_file::parse("items.txt", contents).unwrap();
+        item(&records[0]).err().unwrap().to_string()
+    }
+
+    #[test]
+    fn formula_errors_name_the_record() {
+        assert_eq!(item_error("[7]\nname = Bad\nformula = a.atk *\n"),
+                   "items.txt:3: Invalid damage formula in record [7]: Expected a value, found end of formula at column 8");
+        assert_eq!(item_error("\n[12]\nformula = a.luck\nname = Worse\n"),
+                   "items.txt:3: Invalid damage formula in record [12]: Unknown stat 'luck' at column 3");
+    }
+}
@@ -0,0 +1,234 @@
+use std::collections::BTreeMap;
+use std::fmt;
+
+use database_items::base_item::*;
+use database_items::usable_item::*;
+use database_items::equip_item::EquipItem;
+use database_items::item::{Item, ItemRef};
+use database_items::skill::Skill;
+use database_items::actor::Actor;
+use database_items::state::State;
+use database_items::class::Class;
+use database_items::enemy::Enemy;
+use database_items::troop::Troop;
+use database_items::system::System;
+use battler::Battler;
+use skill_use;
+
+pub mod data_file;
+mod loader;
+
+/// Skill used for the regular "Attack" command
+pub const ATTACK_SKILL_ID: usize = 1;
+
+/// Skill used for the "Guard" command
+pub const GUARD_SKILL_ID: usize = 2;
+
+/// An error found while loading the database, pointing at the offending file and line
+#[derive(Debug)]
+pub struct DatabaseError {
+    pub file: String,
+    pub line: usize, // 0 when the error isn't tied to a specific line
+    pub message: String,
+}
+
+impl DatabaseError {
+    pub fn new<T: fmt::Display>(file: &str, line: usize, message: T) -> DatabaseError {
+        DatabaseError {
+            file: file.to_string(),
+            line: line,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for DatabaseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}: {}", self.file, self.message)
+        } else {
+            write!(f, "{}:{}: {}", self.file, self.line, self.message)
+        }
+    }
+}
+
+/// Holds every table of the game's content, keyed by id
+pub struct Database {
+    pub items: BTreeMap<usize, Item>,
+    pub skills: BTreeMap<usize, Skill>,
+    pub weapons: BTreeMap<usize, EquipItem>,
+    pub armors: BTreeMap<usize, EquipItem>,
+    pub actors: BTreeMap<usize, Actor>,
+    pub states: BTreeMap<usize, State>,
+    pub classes: BTreeMap<usize, Class>,
+    pub enemies: BTreeMap<usize, Enemy>,
+    pub troops: BTreeMap<usize, Troop>,
+    pub system: System,
+}
+
+impl Database {
+    /// Loads every table from the data files in `directory` (e.g. "assets/data")
+    pub fn load(directory: &str) -> Result<Database, DatabaseError> {
+        let path = |name: &str| format!("{}/{}", directory, name);
+
+        let database = Database {
+            items: try!(loader::load_table(&path("items.txt"), loader::item)),
+            skills: try!(loader::load_table(&path("skills.txt"), loader::skill)),
+            weapons: try!(loader::load_table(&path("weapons.txt"), loader::weapon)),
+            armors: try!(loader::load_table(&path("armors.txt"), loader::armor)),
+            actors: try!(loader::load_table(&path("actors.txt"), loader::actor)),
+            states: try!(loader::load_table(&path("states.txt"), loader::state)),
+            classes: try!(loader::load_table(&path("classes.txt"), loader::class)),
+            enemies: try!(loader::load_table(&path("enemies.txt"), loader::enemy)),
+            troops: try!(loader::load_table(&path("troops.txt"), loader::troop)),
+            system: try!(loader::load_single(&path("system.txt"), loader::system)),
+        };
+
+        try!(database.check_references(directory));
+
+        info!("Loaded database: {} items, {} skills, {} weapons, {} armors, {} actors, {} states, {} classes, {} enemies, {} troops",
+              database.items.len(), database.skills.len(), database.weapons.len(), database.armors.len(),
+              database.actors.len(), database.states.len(), database.classes.len(), database.enemies.len(),
+              database.troops.len());
+
+        return Ok(database);
+    }
+
+    /// Makes sure every id used by one table exists in the table it refers to
+    fn check_references(&self, directory: &str) -> Result<(), DatabaseError> {
+        let error = |file: &str, id: usize, message: String| {
+            DatabaseError::new(&format!("{}/{}", directory, file), 0, format!("Record [{}]: {}", id, message))
+        };
+
+        let tables: Vec<(&str, Vec<&BaseItem>)> = vec![
+            ("items.txt", self.items.values().map(|i| &i.base.base).collect()),
+            ("skills.txt", self.skills.values().map(|s| &s.base.base).collect()),
+            ("weapons.txt", self.weapons.values().map(|w| &w.base).collect()),
+            ("armors.txt", self.armors.values().map(|a| &a.base).collect()),
+            ("actors.txt", self.actors.values().map(|a| &a.base).collect()),
+            ("states.txt", self.states.values().map(|s| &s.base).collect()),
+            ("classes.txt", self.classes.values().map(|c| &c.base).collect()),
+            ("enemies.txt", self.enemies.values().map(|e| &e.base).collect()),
+        ];
+
+        for &(file, ref items) in tables.iter() {
+            for item in items.iter() {
+                for feature in item.features.iter() {
+                    if let Err(message) = self.check_feature(feature) {
+                        return Err(error(file, item.id, message));
+                    }
+                }
+            }
+        }
+
+        let usable_items: Vec<(&str, &UsableItem)> = self.items.values().map(|i| ("items.txt", &i.base))
+            .chain(self.skills.values().map(|s| ("skills.txt", &s.base)))
+            .collect();
+
+        for &(file, item) in usable_items.iter() {
+            for effect in item.effects.iter() {
+                match *effect {
+                    Effect::AddState(id, _) | Effect::RemoveState(id, _) if !self.states.contains_key(&id) => {
+                        return Err(error(file, item.base.id, format!("Effect refers to missing state {}", id)));
+                    },
+                    _ => {},
+                }
+            }
+        }
+
+        for class in self.classes.values() {
+            for learning in class.learnings.iter() {
+                if !self.skills.contains_key(&learning.skill_id) {
+                    return Err(error("classes.txt", class.base.id, format!("Learning refers to missing skill {}", learning.skill_id)));
+                }
+            }
+        }
+
+        for actor in self.actors.values() {
+            if !self.classes.contains_key(&actor.class) {
+                return Err(error("actors.txt", actor.base.id, format!("Class {} does not exist", actor.class)));
+            }
+
+            for (slot, id) in actor.equipment.iter() {
+                match self.equip_item(*slot, *id) {
+                    Some(item) if item.slot == *slot || item.slot == EquipSlot::RightHand && *slot == EquipSlot::LeftHand => {},
+                    Some(_) => return Err(error("actors.txt", actor.base.id, format!("Equipment {} doesn't fit slot {:?}", id, slot))),
+                    None => return Err(error("actors.txt", actor.base.id, format!("No equipment {} for slot {:?}", id, slot))),
+                }
+            }
+
+            // Only a dual wielder can start with a weapon in the left hand
+            if let Some(id) = actor.equipment.get(&EquipSlot::LeftHand) {
+                let dual_wield = actor.base.features.iter()
+                    .chain(self.classes[&actor.class].base.features.iter())
+                    .any(|feature| *feature == Feature::DualWield);
+                let two_handed = self.weapons[id].two_handed;
+
+                if !dual_wield || two_handed {
+                    return Err(error("actors.txt", actor.base.id, format!("Weapon {} can't be held in the left hand", id)));
+                }
+            }
+        }
+
+        for enemy in self.enemies.values() {
+            for action in enemy.actions.iter() {
+                if !self.skills.contains_key(&action.skill_id) {
+                    return Err(error("enemies.txt", enemy.base.id, format!("Action refers to missing skill {}", action.skill_id)));
+                }
+
+                // A fresh enemy has full HP, MP and AP, if it can't use the skill now it never can
+                if let Some(battler) = Battler::from_enemy(self, enemy.base.id) {
+                    if let Err(reason) = skill_use::can_use(&battler, action.skill_id, Occasion::Battle) {
+                        warn!("{}/enemies.txt: Record [{}]: Action with skill {} can never be used ({:?})",
+                              directory, enemy.base.id, action.skill_id, reason);
+                    }
+                }
+            }
+
+            for drop in enemy.drops.iter() {
+                if !self.has_item(drop.item) {
+                    return Err(error("enemies.txt", enemy.base.id, format!("Drop refers to missing {:?}", drop.item)));
+                }
+            }
+        }
+
+        for troop in self.troops.values() {
+            for member in troop.members.iter() {
+                if !self.enemies.contains_key(&member.enemy_id) {
+                    return Err(error("troops.txt", troop.id, format!("Member refers to missing enemy {}", member.enemy_id)));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Whether the table an ItemRef points into has an entry for it
+    pub fn has_item(&self, item: ItemRef) -> bool {
+        match item {
+            ItemRef::Item(id) => self.items.contains_key(&id),
+            ItemRef::Weapon(id) => self.weapons.contains_key(&id),
+            ItemRef::Armor(id) => self.armors.contains_key(&id),
+        }
+    }
+
+    fn check_feature(&self, feature: &Feature) -> Result<(), String> {
+        match *feature {
+            Feature::StateResist(id) | Feature::AttackState(id) if !self.states.contains_key(&id) => {
+                Err(format!("Feature refers to missing state {}", id))
+            },
+            Feature::AddSkill(id) | Feature::DisableSkill(id) if !self.skills.contains_key(&id) => {
+                Err(format!("Feature refers to missing skill {}", id))
+            },
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the weapon (hand slots) or armor (all other slots) with the given id
+    pub fn equip_item(&self, slot: EquipSlot, id: usize) -> Option<&EquipItem> {
+        match slot {
+            EquipSlot::LeftHand | EquipSlot::RightHand => self.weapons.get(&id),
+            _ => self.armors.get(&id),
+        }
+    }
+}
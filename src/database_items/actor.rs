@@ -2,10 +2,12 @@ use super::base_item::*;
 use std::collections::BTreeMap;
 
 pub struct Actor {
+    pub base: BaseItem,
     pub class: usize,
     pub initial_level: i32,
     pub max_level: i32,
     pub character_sprite_filename: String,
     pub face_sprite_filename: String,
+    pub face_index: usize, // Which face of the sheet
     pub equipment: BTreeMap<EquipSlot, usize>,
 }
@@ -1,4 +1,6 @@
+use std::str::FromStr;
 
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
 pub enum Element {
     Fire,
     Ice,
@@ -10,6 +12,7 @@ pub enum Element {
     Love,
 }
 
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
 pub enum Parameter {
     Str,
     Vit,
@@ -32,6 +35,7 @@ pub enum Parameter {
     Sdr,
 }
 
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
 pub enum ExParameter {
     Eva,
     Cri,
@@ -39,6 +43,7 @@ pub enum ExParameter {
     Mrf,
 }
 
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
 pub enum SpParameter {
     Hit,
     Tgr,
@@ -50,6 +55,7 @@ pub enum SpParameter {
     Dmr,
 }
 
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
 pub enum SkillType {
     Common,
     Auramancy,
@@ -57,12 +63,14 @@ pub enum SkillType {
     Astromancy,
 }
 
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
 pub enum WeaponType {
     Axe,
     Sword,
     Spear,
 }
 
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
 pub enum ArmorType {
     Helmet,
     Body,
@@ -70,6 +78,7 @@ pub enum ArmorType {
     Boots,
 }
 
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
 pub enum EquipSlot {
     Head,
     Body,
@@ -81,6 +90,7 @@ pub enum EquipSlot {
     Necklace,
 }
 
+#[derive(PartialEq, Clone, Copy, Debug)]
 pub enum Feature {
     ElementRate(Element, f32),
     DebuffRate(f32),
@@ -103,6 +113,7 @@ pub enum Feature {
     EquipArmor(ArmorType),
     LockEquip(EquipSlot),
     SealEquip(EquipSlot),
+    DualWield,
 }
 
 /// Base for all database items
@@ -113,3 +124,52 @@ pub struct BaseItem {
     pub description: String,
     pub features: Vec<Feature>,
 }
+
+enum_from_str!(Element { Fire, Ice, Wind, Earth, Thunder, Water, Hate, Love });
+enum_from_str!(Parameter { Str, Vit, Int, Wis, Dex, Agi, Mhp, Mmp, Map, Atk, Mat, Def, Mdf, Hrg, Mrg, Arg, Ssr, Sdr });
+enum_from_str!(ExParameter { Eva, Cri, Cnt, Mrf });
+enum_from_str!(SpParameter { Hit, Tgr, Rec, Mcr, Acr, Pdr, Mdr, Dmr });
+enum_from_str!(SkillType { Common, Auramancy, Animancy, Astromancy });
+enum_from_str!(WeaponType { Axe, Sword, Spear });
+enum_from_str!(ArmorType { Helmet, Body, Legs, Boots });
+enum_from_str!(EquipSlot { Head, Body, Legs, Feet, LeftHand, RightHand, Ring, Necklace });
+
+/// Parses a feature written as its name followed by its arguments, e.g. "ElementRate Fire 0.5"
+impl FromStr for Feature {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Feature, String> {
+        let (name, args) = try!(super::split_variant(s));
+
+        let (feature, arg_count) = match name {
+            "ElementRate" => (Feature::ElementRate(try!(super::arg(&args, 0)), try!(super::arg(&args, 1))), 2),
+            "DebuffRate" => (Feature::DebuffRate(try!(super::arg(&args, 0))), 1),
+            "StateRate" => (Feature::StateRate(try!(super::arg(&args, 0))), 1),
+            "StateResist" => (Feature::StateResist(try!(super::arg(&args, 0))), 1),
+
+            "Parameter" => (Feature::Parameter(try!(super::arg(&args, 0)), try!(super::arg(&args, 1))), 2),
+            "ExParameter" => (Feature::ExParameter(try!(super::arg(&args, 0)), try!(super::arg(&args, 1))), 2),
+            "SpParameter" => (Feature::SpParameter(try!(super::arg(&args, 0)), try!(super::arg(&args, 1))), 2),
+
+            "AttackElement" => (Feature::AttackElement(try!(super::arg(&args, 0))), 1),
+            "AttackState" => (Feature::AttackState(try!(super::arg(&args, 0))), 1),
+
+            "AddSkillType" => (Feature::AddSkillType(try!(super::arg(&args, 0))), 1),
+            "DisableSkillType" => (Feature::DisableSkillType(try!(super::arg(&args, 0))), 1),
+            "AddSkill" => (Feature::AddSkill(try!(super::arg(&args, 0))), 1),
+            "DisableSkill" => (Feature::DisableSkill(try!(super::arg(&args, 0))), 1),
+
+            "EquipWeapon" => (Feature::EquipWeapon(try!(super::arg(&args, 0))), 1),
+            "EquipArmor" => (Feature::EquipArmor(try!(super::arg(&args, 0))), 1),
+            "LockEquip" => (Feature::LockEquip(try!(super::arg(&args, 0))), 1),
+            "SealEquip" => (Feature::SealEquip(try!(super::arg(&args, 0))), 1),
+            "DualWield" => (Feature::DualWield, 0),
+
+            _ => return Err(format!("Unknown Feature '{}'", name)),
+        };
+
+        try!(super::expect_arg_count(name, &args, arg_count));
+
+        return Ok(feature);
+    }
+}
@@ -0,0 +1,110 @@
+use std::str::FromStr;
+
+use super::base_item::*;
+use std::collections::BTreeMap;
+
+/// The highest level any actor can reach
+pub const MAX_LEVEL: i32 = 99;
+
+/// Value of a parameter at each level: `base + linear * (level - 1) + quadratic * (level - 1)^2`
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct Curve {
+    pub base: f32,
+    pub linear: f32,
+    pub quadratic: f32,
+}
+
+impl Curve {
+    pub fn value(&self, level: i32) -> i32 {
+        let steps = (level - 1) as f32;
+        (self.base + self.linear * steps + self.quadratic * steps * steps).floor() as i32
+    }
+}
+
+/// Total EXP needed to reach each level: `base * (level - 1)^exponent + extra * (level - 1)`
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct ExpCurve {
+    pub base: f32,
+    pub extra: f32,
+    pub exponent: f32,
+}
+
+impl ExpCurve {
+    pub fn total_exp(&self, level: i32) -> i32 {
+        if level <= 1 {
+            return 0;
+        }
+
+        let steps = (level - 1) as f32;
+        (self.base * steps.powf(self.exponent) + self.extra * steps).floor() as i32
+    }
+}
+
+/// A skill learned when reaching a level
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct Learning {
+    pub level: i32,
+    pub skill_id: usize,
+}
+
+/// Defines the class an actor belongs to
+pub struct Class {
+    pub base: BaseItem,
+    pub parameters: BTreeMap<Parameter, Vec<i32>>, // Value at each level, index 0 is level 1
+    pub exp_curve: ExpCurve,
+    pub learnings: Vec<Learning>,
+}
+
+impl Class {
+    /// Value of a parameter at a level, 0 for parameters the class doesn't define
+    pub fn param(&self, parameter: Parameter, level: i32) -> i32 {
+        let index = (level.max(1).min(MAX_LEVEL) - 1) as usize;
+
+        self.parameters.get(&parameter).and_then(|values| values.get(index)).cloned().unwrap_or(0)
+    }
+
+    /// Total EXP needed to reach a level
+    pub fn exp_for_level(&self, level: i32) -> i32 {
+        self.exp_curve.total_exp(level)
+    }
+
+    /// Skills learned when reaching levels in the range (from, to]
+    pub fn skills_learned_between(&self, from: i32, to: i32) -> Vec<usize> {
+        self.learnings.iter().filter(|l| l.level > from && l.level <= to).map(|l| l.skill_id).collect()
+    }
+}
+
+/// Parses a curve written as "base linear" or "base linear quadratic"
+impl FromStr for Curve {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Curve, String> {
+        let args: Vec<&str> = s.split_whitespace().collect();
+
+        if args.len() != 2 && args.len() != 3 {
+            return Err(format!("Curve takes 2 or 3 numbers, found {}", args.len()));
+        }
+
+        Ok(Curve {
+            base: try!(super::arg(&args, 0)),
+            linear: try!(super::arg(&args, 1)),
+            quadratic: if args.len() == 3 { try!(super::arg(&args, 2)) } else { 0.0 },
+        })
+    }
+}
+
+/// Parses an EXP curve written as "base extra exponent"
+impl FromStr for ExpCurve {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ExpCurve, String> {
+        let args: Vec<&str> = s.split_whitespace().collect();
+        try!(super::expect_arg_count("ExpCurve", &args, 3));
+
+        Ok(ExpCurve {
+            base: try!(super::arg(&args, 0)),
+            extra: try!(super::arg(&args, 1)),
+            exponent: try!(super::arg(&args, 2)),
+        })
+    }
+}
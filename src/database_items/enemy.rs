@@ -0,0 +1,98 @@
+use std::str::FromStr;
+
+use super::base_item::*;
+use super::item::ItemRef;
+use std::collections::BTreeMap;
+
+/// An item an enemy may leave behind when defeated
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct Drop {
+    pub item: ItemRef,
+    pub probability: f32,
+}
+
+/// When an enemy may use one of its actions
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum ActionCondition {
+    Always,
+    Turn(i32, i32), // On turn a, then every b turns after it (b = 0 for only turn a)
+    HpBetween(f32, f32), // HP rate, inclusive
+    MpBetween(f32, f32),
+    State(usize),
+    PartyLevel(i32), // Highest party level is at least this
+}
+
+/// A skill an enemy may use, actions with higher ratings are used more often
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct EnemyAction {
+    pub skill_id: usize,
+    pub rating: i32,
+    pub condition: ActionCondition,
+}
+
+pub struct Enemy {
+    pub base: BaseItem,
+    pub battler_sprite_filename: String,
+    pub parameters: BTreeMap<Parameter, i32>,
+    pub exp: i32,
+    pub gold: i32,
+    pub drops: Vec<Drop>,
+    pub actions: Vec<EnemyAction>,
+}
+
+/// Parses a condition written as its name followed by its arguments, e.g. "HpBetween 0.0 0.5"
+impl FromStr for ActionCondition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ActionCondition, String> {
+        let (name, args) = try!(super::split_variant(s));
+
+        let (condition, arg_count) = match name {
+            "Always" => (ActionCondition::Always, 0),
+            "Turn" => (ActionCondition::Turn(try!(super::arg(&args, 0)), try!(super::arg(&args, 1))), 2),
+            "HpBetween" => (ActionCondition::HpBetween(try!(super::arg(&args, 0)), try!(super::arg(&args, 1))), 2),
+            "MpBetween" => (ActionCondition::MpBetween(try!(super::arg(&args, 0)), try!(super::arg(&args, 1))), 2),
+            "State" => (ActionCondition::State(try!(super::arg(&args, 0))), 1),
+            "PartyLevel" => (ActionCondition::PartyLevel(try!(super::arg(&args, 0))), 1),
+            _ => return Err(format!("Unknown ActionCondition '{}'", name)),
+        };
+
+        try!(super::expect_arg_count(name, &args, arg_count));
+
+        return Ok(condition);
+    }
+}
+
+/// Parses a drop written as "<ItemRef> <probability>", e.g. "Item 1 0.5"
+impl FromStr for Drop {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Drop, String> {
+        let args: Vec<&str> = s.split_whitespace().collect();
+        try!(super::expect_arg_count("Drop", &args, 3));
+
+        Ok(Drop {
+            item: try!(format!("{} {}", args[0], args[1]).parse()),
+            probability: try!(super::arg(&args, 2)),
+        })
+    }
+}
+
+/// Parses an action written as "<skill id> <rating> <condition>", e.g. "3 5 HpBetween 0.0 0.5"
+impl FromStr for EnemyAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<EnemyAction, String> {
+        let args: Vec<&str> = s.split_whitespace().collect();
+
+        if args.len() < 3 {
+            return Err(format!("Action takes a skill id, a rating and a condition, found {} value(s)", args.len()));
+        }
+
+        Ok(EnemyAction {
+            skill_id: try!(super::arg(&args, 0)),
+            rating: try!(super::arg(&args, 1)),
+            condition: try!(args[2..].join(" ").parse()),
+        })
+    }
+}
@@ -1,9 +1,18 @@
 use super::base_item::*;
 use std::collections::BTreeMap;
 
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum EquipKind {
+    Weapon(WeaponType),
+    Armor(ArmorType),
+}
+
 /// Base for equippable items (i.e. armor and weapons)
 pub struct EquipItem {
     pub base: BaseItem,
-    pub slot: EquipSlot,
+    pub kind: EquipKind,
+    pub slot: EquipSlot, // Weapons may also go in the left hand when dual wielding
+    pub two_handed: bool, // Only for weapons, keeps the left hand empty
+    pub price: i32,
     pub parameters: BTreeMap<Parameter, i32>,
 }
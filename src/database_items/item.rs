@@ -1,5 +1,8 @@
+use std::str::FromStr;
+
 use super::usable_item::*;
 
+#[derive(PartialEq, Clone, Copy, Debug)]
 pub enum ItemType {
     Regular,
     Key,
@@ -12,3 +15,30 @@ pub struct Item {
     pub price: i32,
     pub consumable: bool,
 }
+
+enum_from_str!(ItemType { Regular, Key });
+
+/// Refers to an entry of the item, weapon or armor table
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
+pub enum ItemRef {
+    Item(usize),
+    Weapon(usize),
+    Armor(usize),
+}
+
+/// Parses an item reference written as "Item 3", "Weapon 1" or "Armor 2"
+impl FromStr for ItemRef {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<ItemRef, String> {
+        let (name, args) = try!(super::split_variant(s));
+        try!(super::expect_arg_count(name, &args, 1));
+
+        match name {
+            "Item" => Ok(ItemRef::Item(try!(super::arg(&args, 0)))),
+            "Weapon" => Ok(ItemRef::Weapon(try!(super::arg(&args, 0)))),
+            "Armor" => Ok(ItemRef::Armor(try!(super::arg(&args, 0)))),
+            _ => Err(format!("Unknown ItemRef '{}'", name)),
+        }
+    }
+}
@@ -1,6 +1,57 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+/// Implements FromStr for an enum whose variants carry no data, matching on the variant names
+macro_rules! enum_from_str {
+    ($name:ident { $($variant:ident),* }) => {
+        impl ::std::str::FromStr for $name {
+            type Err = String;
+
+            fn from_str(s: &str) -> Result<$name, String> {
+                match s {
+                    $(stringify!($variant) => Ok($name::$variant),)*
+                    _ => Err(format!("Unknown {} '{}'", stringify!($name), s)),
+                }
+            }
+        }
+    }
+}
+
 pub mod base_item;
 pub mod usable_item;
 pub mod equip_item;
 pub mod item;
 pub mod skill;
 pub mod actor;
+pub mod state;
+pub mod class;
+pub mod enemy;
+pub mod troop;
+pub mod system;
+
+/// Splits a variant written as "Name arg1 arg2" into its name and arguments
+pub fn split_variant(s: &str) -> Result<(&str, Vec<&str>), String> {
+    let mut words = s.split_whitespace();
+
+    match words.next() {
+        Some(name) => Ok((name, words.collect())),
+        None => Err("Expected a value, found nothing".to_string()),
+    }
+}
+
+/// Parses the argument at `index`, describing what went wrong if it's missing or malformed
+pub fn arg<T>(args: &[&str], index: usize) -> Result<T, String> where T: FromStr, T::Err: Display {
+    match args.get(index) {
+        Some(value) => value.parse().map_err(|e| format!("Invalid argument '{}' at position {}: {}", value, index + 1, e)),
+        None => Err(format!("Missing argument at position {}", index + 1)),
+    }
+}
+
+/// Rejects trailing arguments that a variant doesn't use
+pub fn expect_arg_count(name: &str, args: &[&str], count: usize) -> Result<(), String> {
+    if args.len() != count {
+        return Err(format!("{} takes {} argument(s), found {}", name, count, args.len()));
+    }
+
+    Ok(())
+}
@@ -1,6 +1,9 @@
+use std::str::FromStr;
+
 use super::base_item::*;
 use super::usable_item::*;
 
+#[derive(PartialEq, Clone, Copy, Debug)]
 pub enum Cost {
     Flat(i32),
     Percentage(f32),
@@ -13,5 +16,25 @@ pub struct Skill {
     pub hp_cost: Cost,
     pub mp_cost: Cost,
     pub ap_cost: Cost,
-    pub required_weapon: WeaponType,
+    pub required_weapon: Option<WeaponType>,
+}
+
+/// Parses a cost written as "Flat 10", "Percentage 0.1" or "None"
+impl FromStr for Cost {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Cost, String> {
+        let (name, args) = try!(super::split_variant(s));
+
+        let (cost, arg_count) = match name {
+            "Flat" => (Cost::Flat(try!(super::arg(&args, 0))), 1),
+            "Percentage" => (Cost::Percentage(try!(super::arg(&args, 0))), 1),
+            "None" => (Cost::None, 0),
+            _ => return Err(format!("Unknown Cost '{}'", name)),
+        };
+
+        try!(super::expect_arg_count(name, &args, arg_count));
+
+        return Ok(cost);
+    }
 }
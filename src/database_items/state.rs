@@ -0,0 +1,38 @@
+use super::base_item::*;
+
+/// What a state prevents a battler from doing, ordered from least to most restrictive
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Debug)]
+pub enum Restriction {
+    None,
+    AttackEnemy,
+    AttackAnyone,
+    AttackAlly,
+    CannotAct,
+}
+
+/// When a state's remaining turns count down
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum AutoRemoval {
+    None,
+    ActionEnd,
+    TurnEnd,
+}
+
+/// A status effect that can be inflicted on a battler (e.g. poison or sleep)
+pub struct State {
+    pub base: BaseItem,
+    pub restriction: Restriction,
+    pub priority: i32,
+    pub auto_removal: AutoRemoval,
+    pub min_turns: i32,
+    pub max_turns: i32,
+    pub remove_at_battle_end: bool,
+    pub remove_by_damage: f32, // Chance to be removed when taking HP damage
+    pub slip_damage: f32, // Fraction of max HP lost at the end of each turn
+    pub message_added: String, // Messages are shown after the battler's name
+    pub message_persisting: String,
+    pub message_removed: String,
+}
+
+enum_from_str!(Restriction { None, AttackEnemy, AttackAnyone, AttackAlly, CannotAct });
+enum_from_str!(AutoRemoval { None, ActionEnd, TurnEnd });
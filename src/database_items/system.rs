@@ -0,0 +1,22 @@
+/// How the rates of several elements hitting at once are combined
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum ElementRateMode {
+    Max,
+    Multiply,
+    Average,
+}
+
+enum_from_str!(ElementRateMode { Max, Multiply, Average });
+
+/// Game-wide settings
+pub struct System {
+    pub currency_unit: String,
+    pub max_gold: i32,
+    pub max_stack: u32, // Most copies of a single item the party can carry
+
+    // Parameter rates for each buff and debuff level, their lengths are the most levels that stack
+    pub buff_rates: Vec<f32>,
+    pub debuff_rates: Vec<f32>,
+
+    pub element_rate_mode: ElementRateMode,
+}
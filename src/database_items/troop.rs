@@ -0,0 +1,80 @@
+use std::str::FromStr;
+
+/// An enemy placed on the battle screen
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct TroopMember {
+    pub enemy_id: usize,
+    pub x: i32,
+    pub y: i32,
+}
+
+/// When a battle event page runs
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum PageCondition {
+    TurnEnd,
+    Turn(i32, i32), // On turn a, then every b turns after it (b = 0 for only turn a)
+    EnemyHp(usize, f32), // Troop member index, HP rate at or below
+    ActorHp(usize, f32), // Actor id, HP rate at or below
+}
+
+/// How often a battle event page may run
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum PageSpan {
+    Battle, // Once per battle
+    Turn, // Once per turn
+    Moment, // Every time its condition holds
+}
+
+/// An event that runs during battle when its condition is met
+pub struct TroopPage {
+    pub condition: PageCondition,
+    pub span: PageSpan,
+    pub messages: Vec<String>,
+}
+
+/// A group of enemies fought together
+pub struct Troop {
+    pub id: usize,
+    pub name: String,
+    pub members: Vec<TroopMember>,
+    pub pages: Vec<TroopPage>,
+}
+
+enum_from_str!(PageSpan { Battle, Turn, Moment });
+
+/// Parses a condition written as its name followed by its arguments, e.g. "EnemyHp 0 0.5"
+impl FromStr for PageCondition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<PageCondition, String> {
+        let (name, args) = try!(super::split_variant(s));
+
+        let (condition, arg_count) = match name {
+            "TurnEnd" => (PageCondition::TurnEnd, 0),
+            "Turn" => (PageCondition::Turn(try!(super::arg(&args, 0)), try!(super::arg(&args, 1))), 2),
+            "EnemyHp" => (PageCondition::EnemyHp(try!(super::arg(&args, 0)), try!(super::arg(&args, 1))), 2),
+            "ActorHp" => (PageCondition::ActorHp(try!(super::arg(&args, 0)), try!(super::arg(&args, 1))), 2),
+            _ => return Err(format!("Unknown PageCondition '{}'", name)),
+        };
+
+        try!(super::expect_arg_count(name, &args, arg_count));
+
+        return Ok(condition);
+    }
+}
+
+/// Parses a member written as "<enemy id> <x> <y>"
+impl FromStr for TroopMember {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<TroopMember, String> {
+        let args: Vec<&str> = s.split_whitespace().collect();
+        try!(super::expect_arg_count("Member", &args, 3));
+
+        Ok(TroopMember {
+            enemy_id: try!(super::arg(&args, 0)),
+            x: try!(super::arg(&args, 1)),
+            y: try!(super::arg(&args, 2)),
+        })
+    }
+}
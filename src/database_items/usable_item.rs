@@ -1,5 +1,12 @@
+use std::str::FromStr;
+
 use super::base_item::*;
+use formula::{Formula, FormulaContext};
+
+/// How much critical hits multiply damage by
+pub const CRITICAL_MULTIPLIER: f32 = 3.0;
 
+#[derive(PartialEq, Clone, Copy, Debug)]
 pub enum Scope {
     Noone,
     User,
@@ -9,6 +16,14 @@ pub enum Scope {
     Everyone,
 }
 
+/// Which side of the battle a scope picks its targets from, relative to the user
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum TargetSide {
+    Opponents,
+    Allies,
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
 pub enum Occasion {
     Always,
     Battle,
@@ -16,12 +31,14 @@ pub enum Occasion {
     Never,
 }
 
+#[derive(PartialEq, Clone, Copy, Debug)]
 pub enum HitType {
     CertainHit,
     PhysicalAttack,
     MagicalAttack,
 }
 
+#[derive(PartialEq, Clone, Copy, Debug)]
 pub enum DamageType {
     None,
     HpDamage,
@@ -35,14 +52,44 @@ pub enum DamageType {
     ApDrain,
 }
 
+/// The element of an item's damage
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum DamageElement {
+    None,
+    NormalAttack, // Whatever elements the user attacks with, e.g. from its weapon
+    Element(Element),
+}
+
 pub struct Damage {
     pub damage_type: DamageType,
-    pub element: Element,
-    pub formula: String,
+    pub element: DamageElement,
+    pub formula: Formula,
     pub variance: f32,
     pub can_crit: bool,
 }
 
+impl Damage {
+    /// Evaluates the formula and applies the critical multiplier and variance
+    ///
+    /// The result is never negative, whether it hurts or heals is decided by `damage_type`.
+    pub fn evaluate(&self, context: &mut FormulaContext, critical: bool) -> i32 {
+        let mut value = self.formula.evaluate(context).max(0.0);
+
+        if critical && self.can_crit {
+            value *= CRITICAL_MULTIPLIER;
+        }
+
+        // Two rolls make values near the formula's result more likely than the extremes
+        if self.variance > 0.0 {
+            let amplitude = (value * self.variance).floor() as i32;
+            value += (context.random.range(0, amplitude) + context.random.range(0, amplitude) - amplitude) as f32;
+        }
+
+        value.max(0.0).round() as i32
+    }
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
 pub enum Effect {
     RecoverHp(f32, i32),
     RecoverMp(f32, i32),
@@ -61,8 +108,57 @@ pub enum Effect {
 pub struct UsableItem {
     pub base: BaseItem,
     pub scope: Scope,
+    pub target_side: TargetSide,
+    pub for_dead: bool, // Targets fallen battlers instead of living ones, e.g. for revival
     pub occasion: Occasion,
     pub hit_type: HitType,
     pub damage: Damage,
     pub effects: Vec<Effect>,
 }
+
+enum_from_str!(Scope { Noone, User, OnePerson, WholeParty, OneOrWholeParty, Everyone });
+enum_from_str!(TargetSide { Opponents, Allies });
+enum_from_str!(Occasion { Always, Battle, Menu, Never });
+enum_from_str!(HitType { CertainHit, PhysicalAttack, MagicalAttack });
+enum_from_str!(DamageType { None, HpDamage, MpDamage, ApDamage, HpRecovery, MpRecovery, ApRecovery, HpDrain, MpDrain, ApDrain });
+
+/// Parses "None", "NormalAttack" or the name of an element
+impl FromStr for DamageElement {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<DamageElement, String> {
+        match s.trim() {
+            "None" => Ok(DamageElement::None),
+            "NormalAttack" => Ok(DamageElement::NormalAttack),
+            name => name.parse().map(DamageElement::Element),
+        }
+    }
+}
+
+/// Parses an effect written as its name followed by its arguments, e.g. "RecoverHp 0.25 100"
+impl FromStr for Effect {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Effect, String> {
+        let (name, args) = try!(super::split_variant(s));
+
+        let (effect, arg_count) = match name {
+            "RecoverHp" => (Effect::RecoverHp(try!(super::arg(&args, 0)), try!(super::arg(&args, 1))), 2),
+            "RecoverMp" => (Effect::RecoverMp(try!(super::arg(&args, 0)), try!(super::arg(&args, 1))), 2),
+            "RecoverAp" => (Effect::RecoverAp(try!(super::arg(&args, 0)), try!(super::arg(&args, 1))), 2),
+            "AddState" => (Effect::AddState(try!(super::arg(&args, 0)), try!(super::arg(&args, 1))), 2),
+            "RemoveState" => (Effect::RemoveState(try!(super::arg(&args, 0)), try!(super::arg(&args, 1))), 2),
+            "AddBuff" => (Effect::AddBuff(try!(super::arg(&args, 0)), try!(super::arg(&args, 1))), 2),
+            "AddDebuff" => (Effect::AddDebuff(try!(super::arg(&args, 0)), try!(super::arg(&args, 1))), 2),
+            "RemoveBuff" => (Effect::RemoveBuff(try!(super::arg(&args, 0))), 1),
+            "RemoveDebuff" => (Effect::RemoveDebuff(try!(super::arg(&args, 0))), 1),
+            "RaiseParameter" => (Effect::RaiseParameter(try!(super::arg(&args, 0)), try!(super::arg(&args, 1))), 2),
+            "Escape" => (Effect::Escape, 0),
+            _ => return Err(format!("Unknown Effect '{}'", name)),
+        };
+
+        try!(super::expect_arg_count(name, &args, arg_count));
+
+        return Ok(effect);
+    }
+}
@@ -0,0 +1,237 @@
+use battler::Battler;
+use database_items::base_item::*;
+use database_items::usable_item::Effect;
+use random::Random;
+
+/// What applying effects to a battler actually changed, for display
+#[derive(PartialEq, Clone, Debug)]
+pub struct EffectResult {
+    pub hp: i32,
+    pub mp: i32,
+    pub ap: i32,
+    pub added_states: Vec<usize>,
+    pub removed_states: Vec<usize>,
+    pub added_buffs: Vec<Parameter>,
+    pub added_debuffs: Vec<Parameter>,
+    pub removed_buffs: Vec<Parameter>,
+    pub removed_debuffs: Vec<Parameter>,
+    pub raised_params: Vec<(Parameter, i32)>,
+    pub escaped: bool, // The target wants to flee, it's up to the battle to let it
+}
+
+impl EffectResult {
+    pub fn new() -> EffectResult {
+        EffectResult {
+            hp: 0,
+            mp: 0,
+            ap: 0,
+            added_states: Vec::new(),
+            removed_states: Vec::new(),
+            added_buffs: Vec::new(),
+            added_debuffs: Vec::new(),
+            removed_buffs: Vec::new(),
+            removed_debuffs: Vec::new(),
+            raised_params: Vec::new(),
+            escaped: false,
+        }
+    }
+
+    /// Whether nothing changed, i.e. the effects "had no effect"
+    pub fn is_empty(&self) -> bool {
+        *self == EffectResult::new()
+    }
+}
+
+/// Recovery amount of a RecoverHp/Mp/Ap effect, scaled by the target's recovery rate
+fn recovery(max: i32, rate: f32, flat: i32, target: &Battler) -> i32 {
+    ((max as f32 * rate + flat as f32) * target.sparam(SpParameter::Rec)).round() as i32
+}
+
+/// Applies every effect to a target in order
+pub fn apply_effects(target: &mut Battler, effects: &[Effect], random: &mut Random) -> EffectResult {
+    let mut result = EffectResult::new();
+
+    for effect in effects.iter() {
+        apply_effect(target, effect, random, &mut result);
+    }
+
+    return result;
+}
+
+/// Applies a single effect to a target, adding what changed to `result`
+///
+/// State chances are multiplied by the target's state rate and debuff chances by its debuff
+/// rate. Buff and debuff effects last the given number of turns.
+pub fn apply_effect(target: &mut Battler, effect: &Effect, random: &mut Random, result: &mut EffectResult) {
+    match *effect {
+        Effect::RecoverHp(rate, flat) => {
+            let amount = recovery(target.mhp(), rate, flat, target);
+            let old_hp = target.hp();
+            target.set_hp(old_hp + amount);
+            result.hp += target.hp() - old_hp;
+        },
+
+        Effect::RecoverMp(rate, flat) => {
+            let amount = recovery(target.mmp(), rate, flat, target);
+            let old_mp = target.mp();
+            target.set_mp(old_mp + amount);
+            result.mp += target.mp() - old_mp;
+        },
+
+        Effect::RecoverAp(rate, flat) => {
+            let amount = recovery(target.map(), rate, flat, target);
+            let old_ap = target.ap();
+            target.set_ap(old_ap + amount);
+            result.ap += target.ap() - old_ap;
+        },
+
+        Effect::AddState(state_id, chance) => {
+            let chance = chance * target.state_rate();
+
+            if random.chance(chance) && target.add_state(state_id, random) {
+                result.added_states.push(state_id);
+            }
+        },
+
+        Effect::RemoveState(state_id, chance) => {
+            if random.chance(chance) && target.remove_state(state_id) {
+                result.removed_states.push(state_id);
+            }
+        },
+
+        Effect::AddBuff(parameter, turns) => {
+            if target.add_buff(parameter, turns) {
+                result.added_buffs.push(parameter);
+            }
+        },
+
+        Effect::AddDebuff(parameter, turns) => {
+            if random.chance(target.debuff_rate()) && target.add_debuff(parameter, turns) {
+                result.added_debuffs.push(parameter);
+            }
+        },
+
+        Effect::RemoveBuff(parameter) => {
+            if target.remove_buff(parameter) {
+                result.removed_buffs.push(parameter);
+            }
+        },
+
+        Effect::RemoveDebuff(parameter) => {
+            if target.remove_debuff(parameter) {
+                result.removed_debuffs.push(parameter);
+            }
+        },
+
+        Effect::RaiseParameter(parameter, amount) => {
+            target.raise_param(parameter, amount);
+            result.raised_params.push((parameter, amount));
+        },
+
+        Effect::Escape => result.escaped = true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use database::Database;
+    use random::SequenceRandom;
+
+    const POISON: usize = 1;
+
+    /// The demo database with only the given features on the Slime
+    fn database(features: Vec<Feature>) -> Database {
+        let mut database = Database::load("assets/data").unwrap();
+        database.enemies.get_mut(&1).unwrap().base.features = features;
+
+        return database;
+    }
+
+    fn slime<'a>(database: &'a Database) -> Battler<'a> {
+        Battler::from_enemy(database, 1).unwrap()
+    }
+
+    /// Applies an effect to the Slime with every roll being `roll`
+    fn apply(slime: &mut Battler, effect: Effect, roll: f32) -> EffectResult {
+        apply_effects(slime, &[effect], &mut SequenceRandom::new(vec![roll]))
+    }
+
+    #[test]
+    fn state_rate_scales_state_chances() {
+        let database = database(vec![Feature::StateRate(0.5)]);
+
+        // 0.8 * 0.5 = 0.4
+        let mut slime = slime(&database);
+        assert_eq!(apply(&mut slime, Effect::AddState(POISON, 0.8), 0.39).added_states, vec![POISON]);
+        assert!(slime.has_state(POISON));
+
+        let mut slime = self::slime(&database);
+        assert!(apply(&mut slime, Effect::AddState(POISON, 0.8), 0.4).is_empty());
+        assert!(!slime.has_state(POISON));
+
+        let database = self::database(vec![]);
+        let mut slime = self::slime(&database);
+        assert_eq!(apply(&mut slime, Effect::AddState(POISON, 0.8), 0.79).added_states, vec![POISON]);
+    }
+
+    #[test]
+    fn state_rates_stack() {
+        let database = database(vec![Feature::StateRate(0.5), Feature::StateRate(0.5)]);
+        let mut slime = slime(&database);
+
+        assert!(apply(&mut slime, Effect::AddState(POISON, 1.0), 0.25).is_empty());
+        assert_eq!(apply(&mut slime, Effect::AddState(POISON, 1.0), 0.24).added_states, vec![POISON]);
+    }
+
+    #[test]
+    fn resisted_states_are_never_added() {
+        let database = database(vec![Feature::StateResist(POISON)]);
+        let mut slime = slime(&database);
+
+        assert!(apply(&mut slime, Effect::AddState(POISON, 1.0), 0.0).is_empty());
+        assert!(!slime.has_state(POISON));
+    }
+
+    #[test]
+    fn state_rate_doesnt_affect_removal() {
+        let database = database(vec![Feature::StateRate(0.1)]);
+        let mut slime = slime(&database);
+        slime.add_state(POISON, &mut SequenceRandom::new(vec![0.0]));
+
+        assert!(apply(&mut slime, Effect::RemoveState(POISON, 0.5), 0.5).is_empty());
+        assert_eq!(apply(&mut slime, Effect::RemoveState(POISON, 0.5), 0.49).removed_states, vec![POISON]);
+        assert!(!slime.has_state(POISON));
+    }
+
+    #[test]
+    fn debuff_rate_is_the_debuff_chance() {
+        let database = database(vec![Feature::DebuffRate(0.5)]);
+        let mut slime = slime(&database);
+
+        assert!(apply(&mut slime, Effect::AddDebuff(Parameter::Atk, 3), 0.5).is_empty());
+        assert_eq!(slime.buff_level(Parameter::Atk), 0);
+
+        assert_eq!(apply(&mut slime, Effect::AddDebuff(Parameter::Atk, 3), 0.49).added_debuffs, vec![Parameter::Atk]);
+        assert_eq!(slime.buff_level(Parameter::Atk), -1);
+
+        // Buffs always land
+        assert_eq!(apply(&mut slime, Effect::AddBuff(Parameter::Def, 3), 0.99).added_buffs, vec![Parameter::Def]);
+        assert_eq!(slime.buff_level(Parameter::Def), 1);
+    }
+
+    #[test]
+    fn recovery_is_scaled_by_the_recovery_rate() {
+        let database = database(vec![Feature::SpParameter(SpParameter::Rec, 1.5)]);
+        let mut slime = slime(&database);
+        slime.set_hp(1);
+
+        // (180 * 0.1 + 10) * 1.5 = 42
+        assert_eq!(apply(&mut slime, Effect::RecoverHp(0.1, 10), 0.5).hp, 42);
+        assert_eq!(slime.hp(), 43);
+
+        // Only what was actually recovered counts
+        assert_eq!(apply(&mut slime, Effect::RecoverHp(1.0, 0), 0.5).hp, 180 - 43);
+        assert!(apply(&mut slime, Effect::RecoverHp(1.0, 0), 0.5).is_empty());
+    }
+}
@@ -0,0 +1,170 @@
+use battler::Battler;
+use database_items::base_item::*;
+use database_items::system::ElementRateMode;
+use database_items::usable_item::DamageElement;
+
+/// The elements a battler's normal attacks have, from AttackElement features (e.g. on its weapon)
+pub fn attack_elements(battler: &Battler) -> Vec<Element> {
+    let mut elements = Vec::new();
+
+    for feature in battler.features() {
+        if let Feature::AttackElement(element) = *feature {
+            if !elements.contains(&element) {
+                elements.push(element);
+            }
+        }
+    }
+
+    return elements;
+}
+
+/// The elements an item's damage has when used by `user`
+pub fn damage_elements(user: &Battler, element: DamageElement) -> Vec<Element> {
+    match element {
+        DamageElement::None => Vec::new(),
+        DamageElement::NormalAttack => attack_elements(user),
+        DamageElement::Element(element) => vec![element],
+    }
+}
+
+/// Combines the target's rates for several elements into the rate damage is multiplied by
+///
+/// Negative rates absorb the damage, healing the target instead:
+/// * `Max` takes the highest rate, so the target only absorbs if it absorbs every element
+/// * `Multiply` multiplies the sizes of the rates, and absorbs if any of them is negative
+/// * `Average` takes the mean, so absorbing one element offsets the others
+///
+/// Damage without elements is always at a rate of 1.0.
+pub fn elements_rate(target: &Battler, elements: &[Element], mode: ElementRateMode) -> f32 {
+    if elements.is_empty() {
+        return 1.0;
+    }
+
+    let rates: Vec<f32> = elements.iter().map(|&element| target.element_rate(element)).collect();
+
+    match mode {
+        ElementRateMode::Max => rates.iter().fold(::std::f32::MIN, |max, &rate| max.max(rate)),
+        ElementRateMode::Multiply => {
+            let size = rates.iter().fold(1.0, |product, &rate| product * rate.abs());
+            if rates.iter().any(|&rate| rate < 0.0) { -size } else { size }
+        },
+        ElementRateMode::Average => rates.iter().fold(0.0, |sum, &rate| sum + rate) / rates.len() as f32,
+    }
+}
+
+/// The element rate for an item's damage from `user` to `target`, using the system's rate mode
+pub fn damage_rate(user: &Battler, target: &Battler, element: DamageElement) -> f32 {
+    let elements = damage_elements(user, element);
+    let mode = target.database().system.element_rate_mode;
+
+    elements_rate(target, &elements, mode)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use battle::{Battle, BattleEvent, BattlerRef, Action, ActionKind, Side};
+    use database::Database;
+    use game_variables::GameVariables;
+    use random::SequenceRandom;
+
+    const STARFALL: usize = 3; // Thunder
+
+    /// The demo database with the Hornet attacking with fire, and only the given features on the Slime
+    fn database(slime_features: Vec<Feature>) -> Database {
+        let mut database = Database::load("assets/data").unwrap();
+        database.enemies.get_mut(&1).unwrap().base.features = slime_features;
+        database.enemies.get_mut(&2).unwrap().base.features = vec![Feature::AttackElement(Element::Fire), Feature::AddSkillType(SkillType::Astromancy)];
+
+        return database;
+    }
+
+    fn rates() -> Vec<Feature> {
+        vec![
+            Feature::ElementRate(Element::Fire, 1.5),
+            Feature::ElementRate(Element::Ice, 0.5),
+            Feature::ElementRate(Element::Thunder, -1.0),
+        ]
+    }
+
+    #[test]
+    fn elements_of_damage() {
+        let database = database(vec![]);
+        let hornet = Battler::from_enemy(&database, 2).unwrap();
+        let slime = Battler::from_enemy(&database, 1).unwrap();
+
+        assert_eq!(damage_elements(&hornet, DamageElement::None), vec![]);
+        assert_eq!(damage_elements(&hornet, DamageElement::NormalAttack), vec![Element::Fire]);
+        assert_eq!(damage_elements(&hornet, DamageElement::Element(Element::Ice)), vec![Element::Ice]);
+        assert_eq!(damage_elements(&slime, DamageElement::NormalAttack), vec![]);
+    }
+
+    #[test]
+    fn max_mode_takes_the_highest_rate() {
+        let database = database(rates());
+        let slime = Battler::from_enemy(&database, 1).unwrap();
+
+        assert_eq!(elements_rate(&slime, &[], ElementRateMode::Max), 1.0);
+        assert_eq!(elements_rate(&slime, &[Element::Fire, Element::Ice], ElementRateMode::Max), 1.5);
+        assert_eq!(elements_rate(&slime, &[Element::Ice, Element::Wind], ElementRateMode::Max), 1.0);
+
+        // Absorbing only wins when every element is absorbed
+        assert_eq!(elements_rate(&slime, &[Element::Thunder], ElementRateMode::Max), -1.0);
+        assert_eq!(elements_rate(&slime, &[Element::Ice, Element::Thunder], ElementRateMode::Max), 0.5);
+    }
+
+    #[test]
+    fn multiply_mode_multiplies_the_rates() {
+        let database = database(rates());
+        let slime = Battler::from_enemy(&database, 1).unwrap();
+
+        assert_eq!(elements_rate(&slime, &[], ElementRateMode::Multiply), 1.0);
+        assert_eq!(elements_rate(&slime, &[Element::Fire, Element::Ice], ElementRateMode::Multiply), 0.75);
+
+        // Any absorbed element makes the whole hit absorbed
+        assert_eq!(elements_rate(&slime, &[Element::Fire, Element::Thunder], ElementRateMode::Multiply), -1.5);
+        assert_eq!(elements_rate(&slime, &[Element::Ice, Element::Thunder], ElementRateMode::Multiply), -0.5);
+    }
+
+    #[test]
+    fn average_mode_averages_the_rates() {
+        let database = database(rates());
+        let slime = Battler::from_enemy(&database, 1).unwrap();
+
+        assert_eq!(elements_rate(&slime, &[], ElementRateMode::Average), 1.0);
+        assert_eq!(elements_rate(&slime, &[Element::Fire, Element::Ice], ElementRateMode::Average), 1.0);
+        assert_eq!(elements_rate(&slime, &[Element::Fire, Element::Thunder], ElementRateMode::Average), 0.25);
+        assert_eq!(elements_rate(&slime, &[Element::Ice, Element::Thunder], ElementRateMode::Average), -0.25);
+    }
+
+    #[test]
+    fn damage_rate_uses_the_system_mode() {
+        let mut database = database(rates());
+        database.system.element_rate_mode = ElementRateMode::Multiply;
+        let hornet = Battler::from_enemy(&database, 2).unwrap();
+        let slime = Battler::from_enemy(&database, 1).unwrap();
+
+        assert_eq!(damage_rate(&hornet, &slime, DamageElement::NormalAttack), 1.5);
+        assert_eq!(damage_rate(&hornet, &slime, DamageElement::Element(Element::Thunder)), -1.0);
+        assert_eq!(damage_rate(&hornet, &slime, DamageElement::None), 1.0);
+    }
+
+    #[test]
+    fn negative_rates_absorb_damage() {
+        let mut database = database(vec![Feature::ElementRate(Element::Thunder, -0.5)]);
+        database.skills.get_mut(&STARFALL).unwrap().base.damage.variance = 0.0;
+
+        let hornet = Battler::from_enemy(&database, 2).unwrap();
+        let mut slime = Battler::from_enemy(&database, 1).unwrap();
+        slime.set_hp(100);
+
+        let mut battle = Battle::new(&database, vec![hornet], vec![slime]);
+        let slime = BattlerRef::new(Side::Troop, 0);
+        battle.set_action(BattlerRef::new(Side::Party, 0), Action { kind: ActionKind::Skill(STARFALL), target: None });
+
+        // Starfall deals 100 + 10 * 2 - 8 * 2 = 104 damage, half of it heals instead
+        let events = battle.process_turn(&GameVariables::new(), &mut SequenceRandom::new(vec![0.5]));
+        assert!(events.contains(&BattleEvent::HpChange { target: slime, amount: 52 }));
+        assert_eq!(battle.battler(slime).hp(), 152);
+    }
+}
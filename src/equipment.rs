@@ -0,0 +1,341 @@
+use battler::Battler;
+use database_items::base_item::*;
+use database_items::equip_item::{EquipItem, EquipKind};
+use database_items::item::ItemRef;
+
+/// Why an item can't be equipped
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum EquipError {
+    UnknownItem,
+    WrongSlot,
+    NotPermitted, // The battler's features don't allow this weapon or armor type
+    Locked,
+    Sealed,
+}
+
+/// Items that moved between the inventory and a battler's equipment
+#[derive(PartialEq, Clone, Debug)]
+pub struct EquipDelta {
+    pub to_inventory: Vec<ItemRef>,
+    pub from_inventory: Vec<ItemRef>,
+    pub lost: Vec<ItemRef>, // Items of to_inventory that didn't fit in a full stack
+}
+
+impl EquipDelta {
+    pub fn new() -> EquipDelta {
+        EquipDelta {
+            to_inventory: Vec::new(),
+            from_inventory: Vec::new(),
+            lost: Vec::new(),
+        }
+    }
+
+    /// Adds the moves of another delta to this one
+    pub fn append(&mut self, mut other: EquipDelta) {
+        self.to_inventory.append(&mut other.to_inventory);
+        self.from_inventory.append(&mut other.from_inventory);
+        self.lost.append(&mut other.lost);
+    }
+}
+
+/// Refers to the item in a slot: hand slots hold weapons, the others hold armors
+pub fn item_ref(slot: EquipSlot, id: usize) -> ItemRef {
+    match slot {
+        EquipSlot::LeftHand | EquipSlot::RightHand => ItemRef::Weapon(id),
+        _ => ItemRef::Armor(id),
+    }
+}
+
+pub fn is_slot_locked(battler: &Battler, slot: EquipSlot) -> bool {
+    battler.features().iter().any(|feature| **feature == Feature::LockEquip(slot))
+}
+
+pub fn is_slot_sealed(battler: &Battler, slot: EquipSlot) -> bool {
+    battler.features().iter().any(|feature| **feature == Feature::SealEquip(slot))
+}
+
+pub fn is_dual_wielder(battler: &Battler) -> bool {
+    battler.features().iter().any(|feature| **feature == Feature::DualWield)
+}
+
+/// Whether the battler's class and features allow this kind of weapon or armor
+pub fn is_permitted(battler: &Battler, item: &EquipItem) -> bool {
+    let features = battler.features();
+
+    match item.kind {
+        EquipKind::Weapon(weapon_type) => features.iter().any(|f| **f == Feature::EquipWeapon(weapon_type)),
+        EquipKind::Armor(armor_type) => features.iter().any(|f| **f == Feature::EquipArmor(armor_type)),
+    }
+}
+
+/// Whether an item goes in a slot, hand slots only ever hold weapons
+pub fn fits_slot(battler: &Battler, slot: EquipSlot, item: &EquipItem) -> bool {
+    match slot {
+        EquipSlot::RightHand => true,
+        // The left hand takes a second one-handed weapon
+        EquipSlot::LeftHand => is_dual_wielder(battler) && !item.two_handed,
+        _ => item.slot == slot,
+    }
+}
+
+/// Checks whether a weapon (for hand slots) or an armor (for other slots) may go in a slot
+pub fn can_equip(battler: &Battler, slot: EquipSlot, id: usize) -> Result<(), EquipError> {
+    let item = match battler.database().equip_item(slot, id) {
+        Some(item) => item,
+        None => return Err(EquipError::UnknownItem),
+    };
+
+    if !fits_slot(battler, slot, item) {
+        return Err(EquipError::WrongSlot);
+    }
+
+    if is_slot_sealed(battler, slot) {
+        return Err(EquipError::Sealed);
+    }
+
+    if is_slot_locked(battler, slot) {
+        return Err(EquipError::Locked);
+    }
+
+    if !is_permitted(battler, item) {
+        return Err(EquipError::NotPermitted);
+    }
+
+    Ok(())
+}
+
+/// Equips an item in a slot (or empties it with None), returning the inventory delta
+///
+/// Equipping a two-handed weapon also empties the left hand, and equipping the left hand
+/// takes off a two-handed weapon. Nothing changes when an error is returned.
+pub fn change_equip(battler: &mut Battler, slot: EquipSlot, id: Option<usize>) -> Result<EquipDelta, EquipError> {
+    let mut delta = EquipDelta::new();
+
+    if is_slot_locked(battler, slot) {
+        return Err(EquipError::Locked);
+    }
+
+    // Figure out which other hand has to be emptied
+    let other_hand = match id {
+        Some(id) => {
+            try!(can_equip(battler, slot, id));
+
+            let two_handed = battler.database().equip_item(slot, id).map(|item| item.two_handed).unwrap_or(false);
+            let right_is_two_handed = battler.equipment().get(&EquipSlot::RightHand)
+                .and_then(|&right| battler.database().weapons.get(&right))
+                .map(|item| item.two_handed)
+                .unwrap_or(false);
+
+            if slot == EquipSlot::RightHand && two_handed {
+                Some(EquipSlot::LeftHand)
+            } else if slot == EquipSlot::LeftHand && right_is_two_handed {
+                Some(EquipSlot::RightHand)
+            } else {
+                None
+            }
+        },
+        None => None,
+    };
+
+    if let Some(other_hand) = other_hand {
+        if battler.equipment().contains_key(&other_hand) {
+            if is_slot_locked(battler, other_hand) {
+                return Err(EquipError::Locked);
+            }
+
+            if let Some(old) = battler.set_equipment(other_hand, None) {
+                delta.to_inventory.push(item_ref(other_hand, old));
+            }
+        }
+    }
+
+    if let Some(old) = battler.set_equipment(slot, id) {
+        delta.to_inventory.push(item_ref(slot, old));
+    }
+
+    if let Some(id) = id {
+        delta.from_inventory.push(item_ref(slot, id));
+    }
+
+    return Ok(delta);
+}
+
+/// Takes off everything in sealed slots, and anything the battler can no longer wear there
+///
+/// Call this after the battler's features changed (e.g. a state or class change). Locked slots
+/// are emptied too, e.g. a weapon left in the left hand after losing DualWield.
+pub fn release_unequippable(battler: &mut Battler) -> EquipDelta {
+    let mut delta = EquipDelta::new();
+
+    let slots: Vec<(EquipSlot, usize)> = battler.equipment().iter().map(|(&slot, &id)| (slot, id)).collect();
+
+    for (slot, id) in slots {
+        let wearable = match battler.database().equip_item(slot, id) {
+            Some(item) => fits_slot(battler, slot, item) && is_permitted(battler, item),
+            None => false,
+        };
+
+        if is_slot_sealed(battler, slot) || !wearable {
+            battler.set_equipment(slot, None);
+            delta.to_inventory.push(item_ref(slot, id));
+        }
+    }
+
+    return delta;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use database::Database;
+
+    // Lilly starts with a Short Sword (1) in the right hand and a Star Robe (2) on, her class
+    // may equip swords, helmets and body armor
+    const LILLY: usize = 1;
+    const SHORT_SWORD: usize = 1;
+    const HAND_AXE: usize = 2;
+    const LONG_SPEAR: usize = 3; // Two-handed
+    const LEATHER_CAP: usize = 1;
+    const STAR_ROBE: usize = 2;
+
+    /// The demo database with extra features on Lilly's class
+    fn database(features: Vec<Feature>) -> Database {
+        let mut database = Database::load("assets/data").unwrap();
+        database.classes.get_mut(&1).unwrap().base.features.extend(features);
+
+        return database;
+    }
+
+    fn lilly<'a>(database: &'a Database) -> Battler<'a> {
+        Battler::from_actor(database, LILLY).unwrap()
+    }
+
+    #[test]
+    fn checks_slots_and_permissions() {
+        let database = database(vec![]);
+        let lilly = lilly(&database);
+
+        assert_eq!(can_equip(&lilly, EquipSlot::RightHand, SHORT_SWORD), Ok(()));
+        assert_eq!(can_equip(&lilly, EquipSlot::Head, LEATHER_CAP), Ok(()));
+        assert_eq!(can_equip(&lilly, EquipSlot::RightHand, HAND_AXE), Err(EquipError::NotPermitted));
+        assert_eq!(can_equip(&lilly, EquipSlot::Head, STAR_ROBE), Err(EquipError::WrongSlot));
+        assert_eq!(can_equip(&lilly, EquipSlot::LeftHand, SHORT_SWORD), Err(EquipError::WrongSlot));
+        assert_eq!(can_equip(&lilly, EquipSlot::RightHand, 99), Err(EquipError::UnknownItem));
+    }
+
+    #[test]
+    fn features_grant_permissions() {
+        let database = database(vec![Feature::EquipWeapon(WeaponType::Axe)]);
+        let mut lilly = lilly(&database);
+
+        assert_eq!(can_equip(&lilly, EquipSlot::RightHand, HAND_AXE), Ok(()));
+
+        let delta = change_equip(&mut lilly, EquipSlot::RightHand, Some(HAND_AXE)).unwrap();
+        assert_eq!(delta.to_inventory, vec![ItemRef::Weapon(SHORT_SWORD)]);
+        assert_eq!(delta.from_inventory, vec![ItemRef::Weapon(HAND_AXE)]);
+        assert_eq!(lilly.equipment().get(&EquipSlot::RightHand), Some(&HAND_AXE));
+    }
+
+    #[test]
+    fn change_equip_returns_inventory_delta() {
+        let database = database(vec![]);
+        let mut lilly = lilly(&database);
+
+        let delta = change_equip(&mut lilly, EquipSlot::Head, Some(LEATHER_CAP)).unwrap();
+        assert_eq!(delta.to_inventory, vec![]);
+        assert_eq!(delta.from_inventory, vec![ItemRef::Armor(LEATHER_CAP)]);
+
+        let delta = change_equip(&mut lilly, EquipSlot::Body, None).unwrap();
+        assert_eq!(delta.to_inventory, vec![ItemRef::Armor(STAR_ROBE)]);
+        assert_eq!(delta.from_inventory, vec![]);
+        assert!(!lilly.equipment().contains_key(&EquipSlot::Body));
+
+        // Nothing moves on an error
+        assert_eq!(change_equip(&mut lilly, EquipSlot::RightHand, Some(HAND_AXE)), Err(EquipError::NotPermitted));
+        assert_eq!(lilly.equipment().get(&EquipSlot::RightHand), Some(&SHORT_SWORD));
+    }
+
+    #[test]
+    fn locked_slots_keep_their_item() {
+        let database = database(vec![Feature::LockEquip(EquipSlot::Body)]);
+        let mut lilly = lilly(&database);
+
+        assert_eq!(can_equip(&lilly, EquipSlot::Body, STAR_ROBE), Err(EquipError::Locked));
+        assert_eq!(change_equip(&mut lilly, EquipSlot::Body, None), Err(EquipError::Locked));
+        assert_eq!(lilly.equipment().get(&EquipSlot::Body), Some(&STAR_ROBE));
+
+        // Locking doesn't take anything off
+        assert_eq!(release_unequippable(&mut lilly).to_inventory, vec![]);
+    }
+
+    #[test]
+    fn sealed_slots_are_emptied() {
+        let database = database(vec![Feature::SealEquip(EquipSlot::Body), Feature::SealEquip(EquipSlot::Head)]);
+        let mut lilly = lilly(&database);
+
+        assert_eq!(can_equip(&lilly, EquipSlot::Head, LEATHER_CAP), Err(EquipError::Sealed));
+
+        let delta = release_unequippable(&mut lilly);
+        assert_eq!(delta.to_inventory, vec![ItemRef::Armor(STAR_ROBE)]);
+        assert_eq!(delta.from_inventory, vec![]);
+        assert!(!lilly.equipment().contains_key(&EquipSlot::Body));
+        assert_eq!(lilly.equipment().get(&EquipSlot::RightHand), Some(&SHORT_SWORD));
+    }
+
+    #[test]
+    fn unpermitted_equipment_is_released() {
+        let database = database(vec![]);
+        let mut lilly = lilly(&database);
+
+        // E.g. left over from a class with other permissions, or from losing DualWield
+        lilly.set_equipment(EquipSlot::RightHand, Some(HAND_AXE));
+        lilly.set_equipment(EquipSlot::LeftHand, Some(SHORT_SWORD));
+
+        let delta = release_unequippable(&mut lilly);
+        assert_eq!(delta.to_inventory, vec![ItemRef::Weapon(SHORT_SWORD), ItemRef::Weapon(HAND_AXE)]);
+        assert_eq!(lilly.equipment().get(&EquipSlot::Body), Some(&STAR_ROBE));
+    }
+
+    #[test]
+    fn left_hand_needs_dual_wield_and_a_one_handed_weapon() {
+        let database = database(vec![Feature::DualWield, Feature::EquipWeapon(WeaponType::Spear)]);
+        let mut lilly = lilly(&database);
+
+        assert_eq!(can_equip(&lilly, EquipSlot::LeftHand, LONG_SPEAR), Err(EquipError::WrongSlot));
+
+        let delta = change_equip(&mut lilly, EquipSlot::LeftHand, Some(SHORT_SWORD)).unwrap();
+        assert_eq!(delta.to_inventory, vec![]);
+        assert_eq!(delta.from_inventory, vec![ItemRef::Weapon(SHORT_SWORD)]);
+        assert_eq!(lilly.equipment().get(&EquipSlot::LeftHand), Some(&SHORT_SWORD));
+    }
+
+    #[test]
+    fn two_handed_weapons_take_both_hands() {
+        let database = database(vec![Feature::DualWield, Feature::EquipWeapon(WeaponType::Spear)]);
+        let mut lilly = lilly(&database);
+        lilly.set_equipment(EquipSlot::LeftHand, Some(SHORT_SWORD));
+
+        // The spear empties the left hand too
+        let delta = change_equip(&mut lilly, EquipSlot::RightHand, Some(LONG_SPEAR)).unwrap();
+        assert_eq!(delta.to_inventory, vec![ItemRef::Weapon(SHORT_SWORD), ItemRef::Weapon(SHORT_SWORD)]);
+        assert_eq!(delta.from_inventory, vec![ItemRef::Weapon(LONG_SPEAR)]);
+        assert!(!lilly.equipment().contains_key(&EquipSlot::LeftHand));
+
+        // And a weapon in the left hand takes the spear off
+        let delta = change_equip(&mut lilly, EquipSlot::LeftHand, Some(SHORT_SWORD)).unwrap();
+        assert_eq!(delta.to_inventory, vec![ItemRef::Weapon(LONG_SPEAR)]);
+        assert_eq!(delta.from_inventory, vec![ItemRef::Weapon(SHORT_SWORD)]);
+        assert!(!lilly.equipment().contains_key(&EquipSlot::RightHand));
+    }
+
+    #[test]
+    fn locked_other_hand_blocks_two_handed_weapons() {
+        let database = database(vec![Feature::DualWield, Feature::EquipWeapon(WeaponType::Spear), Feature::LockEquip(EquipSlot::LeftHand)]);
+        let mut lilly = lilly(&database);
+        lilly.set_equipment(EquipSlot::LeftHand, Some(SHORT_SWORD));
+
+        assert_eq!(change_equip(&mut lilly, EquipSlot::RightHand, Some(LONG_SPEAR)), Err(EquipError::Locked));
+        assert_eq!(lilly.equipment().get(&EquipSlot::RightHand), Some(&SHORT_SWORD));
+        assert_eq!(lilly.equipment().get(&EquipSlot::LeftHand), Some(&SHORT_SWORD));
+    }
+}
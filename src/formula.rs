@@ -0,0 +1,756 @@
+use std::fmt;
+use std::str::FromStr;
+
+use database_items::base_item::{Parameter, ExParameter, SpParameter};
+use game_variables::GameVariables;
+use random::Random;
+
+/// Something whose stats can be referenced from a formula (i.e. a battler)
+pub trait FormulaSubject {
+    fn param(&self, parameter: Parameter) -> i32;
+    fn xparam(&self, parameter: ExParameter) -> f32;
+    fn sparam(&self, parameter: SpParameter) -> f32;
+    fn hp(&self) -> i32;
+    fn mp(&self) -> i32;
+    fn ap(&self) -> i32;
+}
+
+/// Everything a formula can look at while being evaluated
+pub struct FormulaContext<'a> {
+    pub a: &'a FormulaSubject,
+    pub b: &'a FormulaSubject,
+    pub variables: &'a GameVariables,
+    pub random: &'a mut Random,
+}
+
+/// A syntax error in a formula, `position` is the character index where it was found
+#[derive(Debug, PartialEq)]
+pub struct FormulaError {
+    pub position: usize,
+    pub message: String,
+}
+
+impl fmt::Display for FormulaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} at column {}", self.message, self.position + 1)
+    }
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+enum Subject {
+    A,
+    B,
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+enum Stat {
+    Param(Parameter),
+    XParam(ExParameter),
+    SParam(SpParameter),
+    Hp,
+    Mp,
+    Ap,
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+enum Function {
+    Min,
+    Max,
+    Abs,
+    Floor,
+    Ceil,
+    Round,
+    Sqrt,
+    Pow,
+    Rand,
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    Equal,
+    NotEqual,
+    And,
+    Or,
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+enum UnaryOp {
+    Negate,
+    Not,
+}
+
+#[derive(Debug)]
+enum Expr {
+    Number(f32),
+    Stat(Subject, Stat),
+    Variable(Box<Expr>),
+    Call(Function, Vec<Expr>),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Conditional(Box<Expr>, Box<Expr>, Box<Expr>),
+}
+
+/// A compiled damage formula, e.g. "a.atk * 4 - b.def * 2"
+///
+/// Formulas can use:
+/// * numbers, `+ - * / %` and parentheses
+/// * `a.<stat>` for the user and `b.<stat>` for the target, where stat is any parameter in
+///   lowercase (`atk`, `mhp`, `eva`, `rec`, ...) or the current `hp`, `mp` and `ap`
+/// * `v[n]` for game variables
+/// * `min`, `max`, `abs`, `floor`, `ceil`, `round`, `sqrt`, `pow` and `rand(low, high)`
+/// * comparisons, `&&`, `||`, `!` and `condition ? then : else`
+///
+/// Truth values are numbers: 0 is false, anything else is true.
+#[derive(Debug)]
+pub struct Formula {
+    source: String,
+    root: Expr,
+}
+
+impl Formula {
+    /// Parses a formula, reporting the first syntax error found
+    pub fn compile(source: &str) -> Result<Formula, FormulaError> {
+        let tokens = try!(tokenize(source));
+        let mut parser = Parser {
+            tokens: tokens,
+            index: 0,
+        };
+
+        let root = try!(parser.expression());
+
+        // Everything has to be consumed, otherwise something like "1 2" would silently become "1"
+        if let Some(&(ref token, position)) = parser.tokens.get(parser.index) {
+            return Err(FormulaError {
+                position: position,
+                message: format!("Unexpected {}", token.describe()),
+            });
+        }
+
+        Ok(Formula {
+            source: source.to_string(),
+            root: root,
+        })
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /// Evaluates the formula
+    pub fn evaluate(&self, context: &mut FormulaContext) -> f32 {
+        evaluate(&self.root, context)
+    }
+}
+
+fn evaluate(expr: &Expr, context: &mut FormulaContext) -> f32 {
+    match *expr {
+        Expr::Number(value) => value,
+
+        Expr::Stat(subject, stat) => {
+            let battler = match subject {
+                Subject::A => context.a,
+                Subject::B => context.b,
+            };
+
+            match stat {
+                Stat::Param(parameter) => battler.param(parameter) as f32,
+                Stat::XParam(parameter) => battler.xparam(parameter),
+                Stat::SParam(parameter) => battler.sparam(parameter),
+                Stat::Hp => battler.hp() as f32,
+                Stat::Mp => battler.mp() as f32,
+                Stat::Ap => battler.ap() as f32,
+            }
+        },
+
+        Expr::Variable(ref index) => {
+            let index = evaluate(index, context);
+
+            if index < 0.0 {
+                error!("Negative variable index {} in formula, defaulting to 0", index);
+                return 0.0;
+            }
+
+            context.variables.get(index as usize) as f32
+        },
+
+        Expr::Call(function, ref args) => {
+            let values: Vec<f32> = args.iter().map(|arg| evaluate(arg, context)).collect();
+
+            match function {
+                Function::Min => values[0].min(values[1]),
+                Function::Max => values[0].max(values[1]),
+                Function::Abs => values[0].abs(),
+                Function::Floor => values[0].floor(),
+                Function::Ceil => values[0].ceil(),
+                Function::Round => values[0].round(),
+                Function::Sqrt => values[0].max(0.0).sqrt(),
+                Function::Pow => values[0].powf(values[1]),
+                Function::Rand => context.random.range(values[0] as i32, values[1] as i32) as f32,
+            }
+        },
+
+        Expr::Unary(op, ref operand) => {
+            let value = evaluate(operand, context);
+
+            match op {
+                UnaryOp::Negate => -value,
+                UnaryOp::Not => truth(value == 0.0),
+            }
+        },
+
+        Expr::Binary(op, ref left, ref right) => {
+            let left = evaluate(left, context);
+
+            // Short circuit the logical operators
+            match op {
+                BinaryOp::And if left == 0.0 => return 0.0,
+                BinaryOp::Or if left != 0.0 => return 1.0,
+                _ => {},
+            }
+
+            let right = evaluate(right, context);
+
+            match op {
+                BinaryOp::Add => left + right,
+                BinaryOp::Sub => left - right,
+                BinaryOp::Mul => left * right,
+                BinaryOp::Div => if right == 0.0 { 0.0 } else { left / right },
+                BinaryOp::Rem => if right == 0.0 { 0.0 } else { left % right },
+                BinaryOp::Less => truth(left < right),
+                BinaryOp::LessEqual => truth(left <= right),
+                BinaryOp::Greater => truth(left > right),
+                BinaryOp::GreaterEqual => truth(left >= right),
+                BinaryOp::Equal => truth(left == right),
+                BinaryOp::NotEqual => truth(left != right),
+                BinaryOp::And | BinaryOp::Or => truth(right != 0.0),
+            }
+        },
+
+        Expr::Conditional(ref condition, ref then, ref otherwise) => {
+            if evaluate(condition, context) != 0.0 {
+                evaluate(then, context)
+            } else {
+                evaluate(otherwise, context)
+            }
+        },
+    }
+}
+
+fn truth(value: bool) -> f32 {
+    if value { 1.0 } else { 0.0 }
+}
+
+#[derive(PartialEq, Clone, Debug)]
+enum Token {
+    Number(f32),
+    Identifier(String),
+    Symbol(&'static str),
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match *self {
+            Token::Number(value) => format!("number {}", value),
+            Token::Identifier(ref name) => format!("'{}'", name),
+            Token::Symbol(symbol) => format!("'{}'", symbol),
+        }
+    }
+}
+
+// Longer symbols first so "<=" isn't read as "<" followed by "="
+const SYMBOLS: &'static [&'static str] = &["<=", ">=", "==", "!=", "&&", "||",
+                                           "+", "-", "*", "/", "%", "(", ")", "[", "]", ",", ".",
+                                           "<", ">", "!", "?", ":"];
+
+/// Splits a formula into tokens, each paired with the character index it starts at
+fn tokenize(source: &str) -> Result<Vec<(Token, usize)>, FormulaError> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    'outer: while i < chars.len() {
+        let c = chars[i];
+
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        if c.is_digit(10) {
+            let start = i;
+            while i < chars.len() && (chars[i].is_digit(10) || chars[i] == '.') {
+                i += 1;
+            }
+
+            let text: String = chars[start..i].iter().cloned().collect();
+            match f32::from_str(&text) {
+                Ok(value) => tokens.push((Token::Number(value), start)),
+                Err(_) => return Err(FormulaError { position: start, message: format!("Invalid number '{}'", text) }),
+            }
+            continue;
+        }
+
+        if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+
+            tokens.push((Token::Identifier(chars[start..i].iter().cloned().collect()), start));
+            continue;
+        }
+
+        for symbol in SYMBOLS.iter() {
+            let length = symbol.chars().count();
+            if i + length <= chars.len() && symbol.chars().zip(chars[i..i + length].iter()).all(|(a, &b)| a == b) {
+                tokens.push((Token::Symbol(symbol), i));
+                i += length;
+                continue 'outer;
+            }
+        }
+
+        return Err(FormulaError { position: i, message: format!("Unexpected character '{}'", c) });
+    }
+
+    return Ok(tokens);
+}
+
+struct Parser {
+    tokens: Vec<(Token, usize)>,
+    index: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.index).map(|&(ref token, _)| token)
+    }
+
+    /// Position of the current token, or the end of the formula
+    fn position(&self) -> usize {
+        match self.tokens.get(self.index) {
+            Some(&(_, position)) => position,
+            None => self.tokens.last().map(|&(_, position)| position + 1).unwrap_or(0),
+        }
+    }
+
+    fn error<T>(&self, message: String) -> Result<T, FormulaError> {
+        Err(FormulaError {
+            position: self.position(),
+            message: message,
+        })
+    }
+
+    fn unexpected<T>(&self, expected: &str) -> Result<T, FormulaError> {
+        match self.peek() {
+            Some(token) => self.error(format!("Expected {}, found {}", expected, token.describe())),
+            None => self.error(format!("Expected {}, found end of formula", expected)),
+        }
+    }
+
+    /// Consumes the given symbol if it's next
+    fn accept(&mut self, symbol: &str) -> bool {
+        let found = match self.peek() {
+            Some(&Token::Symbol(s)) => s == symbol,
+            _ => false,
+        };
+
+        if found {
+            self.index += 1;
+        }
+
+        found
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<(), FormulaError> {
+        if self.accept(symbol) {
+            Ok(())
+        } else {
+            self.unexpected(&format!("'{}'", symbol))
+        }
+    }
+
+    fn expression(&mut self) -> Result<Expr, FormulaError> {
+        let condition = try!(self.or());
+
+        if self.accept("?") {
+            let then = try!(self.expression());
+            try!(self.expect(":"));
+            let otherwise = try!(self.expression());
+
+            return Ok(Expr::Conditional(Box::new(condition), Box::new(then), Box::new(otherwise)));
+        }
+
+        Ok(condition)
+    }
+
+    /// Parses a left associative chain of binary operators, with `next` parsing the operands
+    fn binary_chain<F>(&mut self, operators: &[(&str, BinaryOp)], next: F) -> Result<Expr, FormulaError>
+        where F: Fn(&mut Parser) -> Result<Expr, FormulaError>
+    {
+        let mut left = try!(next(self));
+
+        'outer: loop {
+            for &(symbol, op) in operators.iter() {
+                if self.accept(symbol) {
+                    let right = try!(next(self));
+                    left = Expr::Binary(op, Box::new(left), Box::new(right));
+                    continue 'outer;
+                }
+            }
+
+            return Ok(left);
+        }
+    }
+
+    fn or(&mut self) -> Result<Expr, FormulaError> {
+        self.binary_chain(&[("||", BinaryOp::Or)], Parser::and)
+    }
+
+    fn and(&mut self) -> Result<Expr, FormulaError> {
+        self.binary_chain(&[("&&", BinaryOp::And)], Parser::comparison)
+    }
+
+    fn comparison(&mut self) -> Result<Expr, FormulaError> {
+        self.binary_chain(&[("<=", BinaryOp::LessEqual), (">=", BinaryOp::GreaterEqual),
+                            ("==", BinaryOp::Equal), ("!=", BinaryOp::NotEqual),
+                            ("<", BinaryOp::Less), (">", BinaryOp::Greater)], Parser::additive)
+    }
+
+    fn additive(&mut self) -> Result<Expr, FormulaError> {
+        self.binary_chain(&[("+", BinaryOp::Add), ("-", BinaryOp::Sub)], Parser::multiplicative)
+    }
+
+    fn multiplicative(&mut self) -> Result<Expr, FormulaError> {
+        self.binary_chain(&[("*", BinaryOp::Mul), ("/", BinaryOp::Div), ("%", BinaryOp::Rem)], Parser::unary)
+    }
+
+    fn unary(&mut self) -> Result<Expr, FormulaError> {
+        if self.accept("-") {
+            return Ok(Expr::Unary(UnaryOp::Negate, Box::new(try!(self.unary()))));
+        }
+
+        if self.accept("!") {
+            return Ok(Expr::Unary(UnaryOp::Not, Box::new(try!(self.unary()))));
+        }
+
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, FormulaError> {
+        let token = match self.peek() {
+            Some(token) => token.clone(),
+            None => return self.unexpected("a value"),
+        };
+
+        match token {
+            Token::Number(value) => {
+                self.index += 1;
+                Ok(Expr::Number(value))
+            },
+
+            Token::Symbol("(") => {
+                self.index += 1;
+                let inner = try!(self.expression());
+                try!(self.expect(")"));
+                Ok(inner)
+            },
+
+            Token::Identifier(name) => {
+                let position = self.position();
+                self.index += 1;
+
+                match &name[..] {
+                    "a" => self.stat(Subject::A),
+                    "b" => self.stat(Subject::B),
+                    "v" => {
+                        try!(self.expect("["));
+                        let index = try!(self.expression());
+                        try!(self.expect("]"));
+                        Ok(Expr::Variable(Box::new(index)))
+                    },
+                    _ => self.call(&name, position),
+                }
+            },
+
+            _ => self.unexpected("a value"),
+        }
+    }
+
+    /// Parses the ".stat" part of "a.stat" or "b.stat"
+    fn stat(&mut self, subject: Subject) -> Result<Expr, FormulaError> {
+        try!(self.expect("."));
+
+        let name = match self.peek() {
+            Some(&Token::Identifier(ref name)) => name.clone(),
+            _ => return self.unexpected("a stat name"),
+        };
+
+        match stat_from_name(&name) {
+            Some(stat) => {
+                self.index += 1;
+                Ok(Expr::Stat(subject, stat))
+            },
+            None => self.error(format!("Unknown stat '{}'", name)),
+        }
+    }
+
+    fn call(&mut self, name: &str, position: usize) -> Result<Expr, FormulaError> {
+        let (function, arg_count) = match name {
+            "min" => (Function::Min, 2),
+            "max" => (Function::Max, 2),
+            "abs" => (Function::Abs, 1),
+            "floor" => (Function::Floor, 1),
+            "ceil" => (Function::Ceil, 1),
+            "round" => (Function::Round, 1),
+            "sqrt" => (Function::Sqrt, 1),
+            "pow" => (Function::Pow, 2),
+            "rand" => (Function::Rand, 2),
+            _ => return Err(FormulaError { position: position, message: format!("Unknown name '{}'", name) }),
+        };
+
+        try!(self.expect("("));
+
+        let mut args = Vec::new();
+        if !self.accept(")") {
+            loop {
+                args.push(try!(self.expression()));
+
+                if self.accept(")") {
+                    break;
+                }
+
+                try!(self.expect(","));
+            }
+        }
+
+        if args.len() != arg_count {
+            return Err(FormulaError {
+                position: position,
+                message: format!("{} takes {} argument(s), found {}", name, arg_count, args.len()),
+            });
+        }
+
+        Ok(Expr::Call(function, args))
+    }
+}
+
+/// Maps a lowercase stat name (e.g. "atk", "eva", "rec", "hp") to the stat it refers to
+fn stat_from_name(name: &str) -> Option<Stat> {
+    match name {
+        "hp" => return Some(Stat::Hp),
+        "mp" => return Some(Stat::Mp),
+        "ap" => return Some(Stat::Ap),
+        _ => {},
+    }
+
+    // Parameter names are the enum variants in lowercase
+    let mut chars = name.chars();
+    let capitalized = match chars.next() {
+        Some(first) if name.chars().all(|c| c.is_lowercase()) => {
+            first.to_uppercase().chain(chars).collect::<String>()
+        },
+        _ => return None,
+    };
+
+    if let Ok(parameter) = Parameter::from_str(&capitalized) {
+        Some(Stat::Param(parameter))
+    } else if let Ok(parameter) = ExParameter::from_str(&capitalized) {
+        Some(Stat::XParam(parameter))
+    } else if let Ok(parameter) = SpParameter::from_str(&capitalized) {
+        Some(Stat::SParam(parameter))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use random::SequenceRandom;
+
+    /// Battler stand-in with a few interesting stats, everything else is 0
+    struct Stub {
+        atk: i32,
+        def: i32,
+        hp: i32,
+        cri: f32,
+        rec: f32,
+    }
+
+    impl FormulaSubject for Stub {
+        fn param(&self, parameter: Parameter) -> i32 {
+            match parameter {
+                Parameter::Atk => self.atk,
+                Parameter::Def => self.def,
+                _ => 0,
+            }
+        }
+
+        fn xparam(&self, parameter: ExParameter) -> f32 {
+            if parameter == ExParameter::Cri { self.cri } else { 0.0 }
+        }
+
+        fn sparam(&self, parameter: SpParameter) -> f32 {
+            if parameter == SpParameter::Rec { self.rec } else { 0.0 }
+        }
+
+        fn hp(&self) -> i32 {
+            self.hp
+        }
+
+        fn mp(&self) -> i32 {
+            0
+        }
+
+        fn ap(&self) -> i32 {
+            0
+        }
+    }
+
+    /// Compiles and evaluates a formula, rolling `roll` for every random number
+    fn evaluate_with(source: &str, roll: f32) -> f32 {
+        let a = Stub { atk: 30, def: 5, hp: 40, cri: 0.25, rec: 1.0 };
+        let b = Stub { atk: 10, def: 12, hp: 80, cri: 0.0, rec: 1.5 };
+
+        let mut variables = GameVariables::new();
+        variables.set(2, 7);
+
+        let mut random = SequenceRandom::new(vec![roll]);
+        let mut context = FormulaContext {
+            a: &a,
+            b: &b,
+            variables: &variables,
+            random: &mut random,
+        };
+
+        Formula::compile(source).unwrap().evaluate(&mut context)
+    }
+
+    fn eval(source: &str) -> f32 {
+        evaluate_with(source, 0.0)
+    }
+
+    fn compile_error(source: &str) -> FormulaError {
+        Formula::compile(source).unwrap_err()
+    }
+
+    fn error(position: usize, message: &str) -> FormulaError {
+        FormulaError {
+            position: position,
+            message: message.to_string(),
+        }
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(eval("1 + 2 * 3"), 7.0);
+        assert_eq!(eval("(1 + 2) * 3"), 9.0);
+        assert_eq!(eval("7 % 4 * 2 + 1"), 7.0);
+        assert_eq!(eval("1 + 2 < 4"), 1.0);
+        assert_eq!(eval("2 * 3 == 6 && 1 > 2"), 0.0);
+        assert_eq!(eval("1 || 0 && 0"), 1.0);
+        assert_eq!(eval("0 || 1 && 0"), 0.0);
+    }
+
+    #[test]
+    fn left_associativity() {
+        assert_eq!(eval("10 - 4 - 3"), 3.0);
+        assert_eq!(eval("12 / 4 / 3"), 1.0);
+        assert_eq!(eval("20 % 7 % 4"), 2.0);
+        assert_eq!(eval("3 > 2 > 0"), 1.0);
+        assert_eq!(eval("1 < 2 == 1"), 1.0);
+    }
+
+    #[test]
+    fn unary_operators() {
+        assert_eq!(eval("-3 * -2"), 6.0);
+        assert_eq!(eval("--4"), 4.0);
+        assert_eq!(eval("-2 * 3 + 1"), -5.0);
+        assert_eq!(eval("2 - -1"), 3.0);
+        assert_eq!(eval("-(2 + 3)"), -5.0);
+        assert_eq!(eval("!0"), 1.0);
+        assert_eq!(eval("!5"), 0.0);
+        assert_eq!(eval("!!5"), 1.0);
+    }
+
+    #[test]
+    fn conditionals() {
+        assert_eq!(eval("1 ? 2 : 3"), 2.0);
+        assert_eq!(eval("0 ? 2 : 3"), 3.0);
+        assert_eq!(eval("1 + 1 == 2 ? 5 : 6"), 5.0);
+        assert_eq!(eval("0 ? 1 : 0 ? 2 : 3"), 3.0);
+        assert_eq!(eval("1 ? 0 ? 1 : 2 : 3"), 2.0);
+        assert_eq!(eval("a.hp < b.hp ? 100 : 10"), 100.0);
+    }
+
+    #[test]
+    fn stats_and_variables() {
+        assert_eq!(eval("a.atk * 4 - b.def * 2"), 96.0);
+        assert_eq!(eval("a.hp + b.hp"), 120.0);
+        assert_eq!(eval("a.cri * 4 + b.rec"), 2.5);
+        assert_eq!(eval("v[2] * 2 + v[5]"), 14.0);
+        assert_eq!(eval("v[1 + 1]"), 7.0);
+    }
+
+    #[test]
+    fn functions() {
+        assert_eq!(eval("min(3, 5)"), 3.0);
+        assert_eq!(eval("max(3, 5)"), 5.0);
+        assert_eq!(eval("abs(-4)"), 4.0);
+        assert_eq!(eval("floor(2.7)"), 2.0);
+        assert_eq!(eval("floor(-2.5)"), -3.0);
+        assert_eq!(eval("ceil(2.1)"), 3.0);
+        assert_eq!(eval("round(2.5)"), 3.0);
+        assert_eq!(eval("round(2.4)"), 2.0);
+        assert_eq!(eval("sqrt(16)"), 4.0);
+        assert_eq!(eval("sqrt(-4)"), 0.0);
+        assert_eq!(eval("pow(2, 10)"), 1024.0);
+        assert_eq!(eval("max(min(a.atk, 20), b.def)"), 20.0);
+    }
+
+    #[test]
+    fn rand_is_inclusive() {
+        assert_eq!(evaluate_with("rand(1, 6)", 0.0), 1.0);
+        assert_eq!(evaluate_with("rand(1, 6)", 0.5), 4.0);
+        assert_eq!(evaluate_with("rand(1, 6)", 0.99), 6.0);
+        assert_eq!(evaluate_with("rand(5, 5)", 0.99), 5.0);
+        assert_eq!(evaluate_with("rand(5, 2)", 0.99), 5.0);
+    }
+
+    #[test]
+    fn division_by_zero_is_zero() {
+        assert_eq!(eval("5 / 0"), 0.0);
+        assert_eq!(eval("5 % 0"), 0.0);
+        assert_eq!(eval("a.atk / (b.def - 12) + 1"), 1.0);
+    }
+
+    #[test]
+    fn syntax_errors() {
+        assert_eq!(compile_error("1 +"), error(3, "Expected a value, found end of formula"));
+        assert_eq!(compile_error("1 2"), error(2, "Unexpected number 2"));
+        assert_eq!(compile_error("(1 + 2"), error(6, "Expected ')', found end of formula"));
+        assert_eq!(compile_error("1 ? 2"), error(5, "Expected ':', found end of formula"));
+        assert_eq!(compile_error("a atk"), error(2, "Expected '.', found 'atk'"));
+        assert_eq!(compile_error("a.foo"), error(2, "Unknown stat 'foo'"));
+        assert_eq!(compile_error("b.Atk"), error(2, "Unknown stat 'Atk'"));
+        assert_eq!(compile_error("2 * foo(1)"), error(4, "Unknown name 'foo'"));
+        assert_eq!(compile_error("min(1)"), error(0, "min takes 2 argument(s), found 1"));
+        assert_eq!(compile_error("1 $ 2"), error(2, "Unexpected character '$'"));
+        assert_eq!(compile_error("1.2.3"), error(0, "Invalid number '1.2.3'"));
+        assert_eq!(compile_error(""), error(0, "Expected a value, found end of formula"));
+    }
+
+    #[test]
+    fn error_display_uses_columns() {
+        assert_eq!(compile_error("1 +").to_string(), "Expected a value, found end of formula at column 4");
+    }
+}
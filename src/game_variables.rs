@@ -0,0 +1,25 @@
+/// Numbered variables that events and scripts use to track game progress
+pub struct GameVariables {
+    values: Vec<i32>,
+}
+
+impl GameVariables {
+    pub fn new() -> GameVariables {
+        GameVariables {
+            values: Vec::new(),
+        }
+    }
+
+    /// Returns the value of a variable, variables that were never set are 0
+    pub fn get(&self, id: usize) -> i32 {
+        self.values.get(id).cloned().unwrap_or(0)
+    }
+
+    pub fn set(&mut self, id: usize, value: i32) {
+        if id >= self.values.len() {
+            self.values.resize(id + 1, 0);
+        }
+
+        self.values[id] = value;
+    }
+}
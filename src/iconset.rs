@@ -14,6 +14,10 @@ impl Iconset {
         }
     }
 
+    pub fn icon_size(&self) -> u32 {
+        self.icon_size
+    }
+
     pub fn  get(&self, index: usize) -> Sprite {
         let Vector2u {x: w, y: h} = self.texture.get_size();
         let cols = w / self.icon_size;
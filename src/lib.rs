@@ -0,0 +1,29 @@
+#[macro_use]
+extern crate log;
+extern crate sfml;
+
+// Game logic, none of it needs a window
+pub mod database_items;
+pub mod database;
+pub mod random;
+pub mod game_variables;
+pub mod formula;
+pub mod battler;
+pub mod battle;
+pub mod equipment;
+pub mod effect;
+pub mod element;
+pub mod targeting;
+pub mod skill_use;
+pub mod party;
+pub mod text_markup;
+
+// Windows and graphics
+pub mod iconset;
+pub mod window;
+pub mod animation;
+pub mod text_window;
+pub mod choice_window;
+pub mod number_input_window;
+pub mod selectable_window;
+pub mod game_settings_manager;
@@ -1,20 +1,22 @@
-
-#[macro_use]
 extern crate log;
 extern crate fern;
 extern crate sfml;
-
-mod iconset;
-mod window;
-mod text_window;
-mod game_settings_manager;
-mod database_items;
+extern crate rpg;
 
 use sfml::system::{Clock};
-use sfml::graphics::{Drawable, RenderStates, Text, RenderWindow, Color, RenderTarget};
+use sfml::graphics::{RenderStates, Text, RenderWindow, Color, RenderTarget, Texture};
 use sfml::window::{VideoMode, ContextSettings, event, window_style, Key};
 
-use game_settings_manager::GameSettingsManager;
+use rpg::{window, text_window, selectable_window};
+use rpg::game_settings_manager::GameSettingsManager;
+use rpg::database::Database;
+use rpg::game_variables::GameVariables;
+use rpg::text_window::{TextContext, TextWindowState, Face, FaceSide};
+use rpg::choice_window::{ChoiceWindow, Choice, CancelBehavior, ChoiceResult};
+use rpg::number_input_window::NumberInputWindow;
+use rpg::selectable_window::SelectableWindow;
+use rpg::animation::{Easing, Transition, Edge};
+use rpg::iconset::Iconset;
 
 
 const WINDOW_HEIGHT: u32 = 800;
@@ -37,6 +39,11 @@ fn main() {
         panic!("Failed to initialize global logger: {}", e);
     }
 
+    let database = match Database::load("assets/data") {
+        Ok(database) => database,
+        Err(e) => panic!("Failed to load database: {}", e),
+    };
+
     let settings = ContextSettings::default();
     let mut window = RenderWindow::new(VideoMode::new_init(WINDOW_HEIGHT, WINDOW_WIDTH, 32), "SFML", window_style::CLOSE, &settings).unwrap();
     window.set_vertical_sync_enabled(true);
@@ -44,9 +51,51 @@ fn main() {
 
     let settings = GameSettingsManager::new("assets/fonts/sansation.ttf", 18, "assets/textures/icons.png", "assets/textures/window_skin.png");
 
-    let msg = "Hello my name is \\p[30]L\\p[30]i\\p[30]l\\p[30]l\\p[30]y\nI like \\i[70]\\p[10]\\i[71]\\p[10]\\i[72]";
+    let variables = GameVariables::new();
+    let context = TextContext {
+        database: &database,
+        variables: &variables,
+        party: &[],
+    };
+
+    let msg = text_window::substitute("Hello my name is \\w[30]\\n[1]\\!\nI like \\i[70]\\.\\i[71]\\.\\i[72] and \\g", &context);
+
+    // Lilly speaks, with her face if she has one
+    let lilly = database.actors.get(&1);
+    let face_sheet = match lilly {
+        Some(lilly) if !lilly.face_sprite_filename.is_empty() => {
+            Texture::new_from_file(&lilly.face_sprite_filename).map(|texture| Iconset::new(texture, 96))
+        },
+        _ => None,
+    };
+
+    let mut text_window = text_window::TextWindow::new(&settings, &msg, 0.0, 100.0, 500.0, 200.0);
+    text_window.set_speaker(lilly.map(|lilly| &lilly.base.name[..]));
+
+    if let (Some(lilly), Some(sheet)) = (lilly, face_sheet.as_ref()) {
+        text_window.set_face(Some(Face {
+            sheet: sheet,
+            index: lilly.face_index,
+            side: FaceSide::Left,
+        }));
+    }
 
-    let mut text_window = text_window::TextWindow::new(&settings, msg, 0.0, 50.0, 500.0, 200.0);
+    // Asked once the message is done, the number only if the player answers yes
+    let mut choice_window: Option<ChoiceWindow> = None;
+    let mut number_window: Option<NumberInputWindow> = None;
+
+    // Every item in the database, picked with the arrow keys while no question is asked
+    let items: Vec<_> = database.items.values().map(|item| (item.base.base.icon_index, item.base.base.name.clone())).collect();
+    let mut item_text = Text::new_init("", &settings.font, settings.default_font_size).unwrap();
+    let mut item_window = SelectableWindow::new(&settings.window_skin, 500.0, 50.0, 300.0, 110.0, 2, 24.0);
+    item_window.window().set_transition(Transition::Slide(Edge::Right));
+    item_window.window().set_easing(Easing::EaseOut);
+    item_window.window().set_durations(0.3, 0.2);
+    item_window.window().set_tone(Color::new_rgba(255, 190, 190, 255));
+    item_window.set_items(items.len(), Box::new(|index, contents, rect| {
+        let (icon, ref name) = items[index];
+        selectable_window::draw_icon_item(contents, rect, &mut item_text, &settings.iconset, icon, name, None);
+    }));
 
     let mut clock = Clock::new();
 
@@ -68,6 +117,65 @@ fn main() {
                     },
                     Key::D => {
                         text_window.change_text("Test 2");
+                        choice_window = None;
+                        number_window = None;
+                    },
+                    Key::Return => {
+                        if let Some(ref mut number_window) = number_window {
+                            number_window.confirm();
+                        } else if let Some(ref mut choice_window) = choice_window {
+                            choice_window.confirm();
+                        } else {
+                            text_window.confirm();
+                        }
+                    },
+                    Key::BackSpace => {
+                        if let Some(ref mut choice_window) = choice_window {
+                            choice_window.cancel();
+                        }
+                    },
+                    Key::Up => {
+                        if let Some(ref mut number_window) = number_window {
+                            number_window.increase();
+                        } else if let Some(ref mut choice_window) = choice_window {
+                            choice_window.move_up();
+                        } else {
+                            item_window.move_up();
+                        }
+                    },
+                    Key::Down => {
+                        if let Some(ref mut number_window) = number_window {
+                            number_window.decrease();
+                        } else if let Some(ref mut choice_window) = choice_window {
+                            choice_window.move_down();
+                        } else {
+                            item_window.move_down();
+                        }
+                    },
+                    Key::H => {
+                        let visible = item_window.window().is_visible();
+                        item_window.window().set_visible(!visible);
+                    },
+                    Key::F => {
+                        // Fade the message's text out, or back in
+                        let opacity = if text_window.window().get_content_opacity() > 0.5 { 0.0 } else { 1.0 };
+                        text_window.window().fade_content_opacity(opacity, 0.5);
+                    },
+                    Key::PageUp => item_window.page_up(),
+                    Key::PageDown => item_window.page_down(),
+                    Key::Left => {
+                        if let Some(ref mut number_window) = number_window {
+                            number_window.move_left();
+                        } else {
+                            item_window.move_left();
+                        }
+                    },
+                    Key::Right => {
+                        if let Some(ref mut number_window) = number_window {
+                            number_window.move_right();
+                        } else {
+                            item_window.move_right();
+                        }
                     },
                     _ => {}
                 },
@@ -85,9 +193,35 @@ fn main() {
         window.clear(&Color::black());
 
         // Update and draw textbox
-        text_window.update();
+        text_window.update(delta_time);
         window.draw(&text_window);
 
+        // Update and draw the item list
+        item_window.update(delta_time);
+        window.draw(&item_window);
+
+        // Update and draw choices
+        if text_window.get_state() == TextWindowState::Done && choice_window.is_none() {
+            let choices = vec![Choice::new("Yes"), Choice::new("No"), Choice::disabled("Maybe")];
+            let mut new_window = ChoiceWindow::new(&settings, choices, 0, CancelBehavior::Choose(1), 0.0, 310.0);
+            new_window.window().set_transition(Transition::Fade);
+            choice_window = Some(new_window);
+        }
+
+        if let Some(ref mut choice_window) = choice_window {
+            if choice_window.result() == Some(ChoiceResult::Chosen(0)) && number_window.is_none() {
+                number_window = Some(NumberInputWindow::new(&settings, 3, 0, 150.0, 310.0));
+            }
+
+            choice_window.update(delta_time);
+            window.draw(&*choice_window);
+        }
+
+        if let Some(ref mut number_window) = number_window {
+            number_window.update(delta_time);
+            window.draw(&*number_window);
+        }
+
         // Draw FPS counter
         window.draw_text(&fps_text, &mut RenderStates::default());
 
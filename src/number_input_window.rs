@@ -0,0 +1,154 @@
+use sfml::graphics::{Transformable, Text, Drawable, Color, RenderTarget, RenderStates, FloatRect};
+
+use window::{WindowState, Window, CONTENT_PADDING};
+use game_settings_manager::GameSettingsManager;
+
+/// Most digits a number can have, so it fits in a u32
+const MAX_DIGITS: u32 = 9;
+
+// A window for the player to enter a number digit by digit
+// Left and right pick a digit, up and down change it. The caller reads the number with `result`
+// once it's confirmed.
+pub struct NumberInputWindow<'a> {
+    window: Window<'a>,
+    text: Text<'a>,
+    digits: u32,
+    value: u32,
+    index: u32, // The selected digit, from the left
+    result: Option<u32>,
+    digit_width: f32,
+}
+
+impl<'a> NumberInputWindow<'a> {
+    /// Returns a new NumberInputWindow at (x, y) for a number of `digits` digits, starting at `value`
+    pub fn new(settings: &'a GameSettingsManager, digits: u32, value: u32, x: f32, y: f32) -> NumberInputWindow<'a> {
+        let digits = if digits < 1 || digits > MAX_DIGITS {
+            error!("Illegal digit count {}, must be between 1 and {}", digits, MAX_DIGITS);
+            if digits < 1 { 1 } else { MAX_DIGITS }
+        } else {
+            digits
+        };
+
+        let max_value = 10u32.pow(digits) - 1;
+        if value > max_value {
+            error!("Number {} has more than {} digits, defaulting to {}", value, digits, max_value);
+        }
+
+        let mut text = Text::new_init("0", &settings.font, settings.default_font_size).unwrap();
+        let digit_width = text.get_local_bounds().width + 4.0;
+        text.set_string("");
+
+        let padding = CONTENT_PADDING as f32 * 2.0;
+        let window = Window::new(&settings.window_skin, x, y, digit_width * digits as f32 + padding * 2.0, settings.default_font_size as f32 + padding * 2.0);
+
+        let mut number_window = NumberInputWindow {
+            window: window,
+            text: text,
+            digits: digits,
+            value: if value > max_value { max_value } else { value },
+            index: digits - 1,
+            result: None,
+            digit_width: digit_width,
+        };
+
+        number_window.refresh();
+
+        return number_window;
+    }
+
+    /// The number the player entered, once they confirmed it
+    pub fn result(&self) -> Option<u32> {
+        self.result
+    }
+
+    pub fn value(&self) -> u32 {
+        self.value
+    }
+
+    /// The window under the digits, e.g. to change its transition
+    pub fn window(&mut self) -> &mut Window<'a> {
+        &mut self.window
+    }
+
+    pub fn update(&mut self, delta_time: f32) {
+        self.window.update(delta_time);
+    }
+
+    fn is_active(&self) -> bool {
+        self.window.is_open() && self.result.is_none()
+    }
+
+    /// Place value of the selected digit, e.g. 100 for the hundreds
+    fn place(&self) -> u32 {
+        10u32.pow(self.digits - 1 - self.index)
+    }
+
+    fn digit(&self) -> u32 {
+        self.value / self.place() % 10
+    }
+
+    /// Changes the selected digit by `delta`, wrapping between 9 and 0
+    fn change_digit(&mut self, delta: u32) {
+        if self.is_active() {
+            let digit = self.digit();
+            let place = self.place();
+            self.value = self.value - digit * place + (digit + delta) % 10 * place;
+            self.refresh();
+        }
+    }
+
+    pub fn increase(&mut self) {
+        self.change_digit(1);
+    }
+
+    pub fn decrease(&mut self) {
+        self.change_digit(9);
+    }
+
+    pub fn move_left(&mut self) {
+        if self.is_active() {
+            self.index = (self.index + self.digits - 1) % self.digits;
+            self.refresh();
+        }
+    }
+
+    pub fn move_right(&mut self) {
+        if self.is_active() {
+            self.index = (self.index + 1) % self.digits;
+            self.refresh();
+        }
+    }
+
+    pub fn confirm(&mut self) {
+        if self.is_active() {
+            info!("Number entered: {}", self.value);
+
+            self.result = Some(self.value);
+            self.window.set_cursor_rect(None);
+            self.window.set_state(WindowState::Closing);
+        }
+    }
+
+    /// Redraws the digits and puts the cursor on the selected one
+    fn refresh(&mut self) {
+        self.window.get_contents().clear(&Color::new_rgba(0, 0, 0, 0));
+
+        let number = format!("{:01$}", self.value, self.digits as usize);
+
+        for (index, digit) in number.chars().enumerate() {
+            self.text.set_string(&digit.to_string());
+            self.text.set_position2f(CONTENT_PADDING as f32 + index as f32 * self.digit_width, 0.0);
+            self.window.get_contents().draw(&self.text);
+        }
+
+        let cursor = FloatRect::new(CONTENT_PADDING as f32 + self.index as f32 * self.digit_width - 2.0, 0.0,
+                                    self.digit_width, self.window.get_content_size().y);
+        self.window.set_cursor_rect(Some(cursor));
+    }
+}
+
+impl<'a> Drawable for NumberInputWindow<'a> {
+    fn draw<RT: RenderTarget>(&self, target: &mut RT, rs: &mut RenderStates) {
+        target.draw_with_renderstates(&self.window, rs);
+    }
+}
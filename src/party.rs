@@ -0,0 +1,563 @@
+use std::collections::BTreeMap;
+
+use battle::Battle;
+use battler::Battler;
+use database::Database;
+use database_items::base_item::EquipSlot;
+use database_items::item::{ItemRef, ItemType};
+use equipment::{self, EquipDelta, EquipError};
+use random::Random;
+
+/// Why an inventory operation was refused
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum InventoryError {
+    UnknownItem,
+    NotEnough,
+    KeyItem, // Key items can't be sold or discarded
+    Equip(EquipError),
+}
+
+/// Groups of items shown on separate menu pages
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum ItemCategory {
+    All,
+    Items,
+    KeyItems,
+    Weapons,
+    Armors,
+}
+
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum ItemSort {
+    Id,
+    Name,
+    Count,
+}
+
+/// The player's party: its members, gold and inventory
+pub struct Party<'a> {
+    database: &'a Database,
+    members: Vec<Battler<'a>>,
+    gold: i32,
+    inventory: BTreeMap<ItemRef, u32>,
+}
+
+impl<'a> Party<'a> {
+    pub fn new(database: &'a Database) -> Party<'a> {
+        Party {
+            database: database,
+            members: Vec::new(),
+            gold: 0,
+            inventory: BTreeMap::new(),
+        }
+    }
+
+    pub fn members(&self) -> &[Battler<'a>] {
+        &self.members
+    }
+
+    pub fn member_mut(&mut self, index: usize) -> Option<&mut Battler<'a>> {
+        self.members.get_mut(index)
+    }
+
+    /// Adds an actor of the database to the party, returning false if the id is invalid
+    pub fn add_actor(&mut self, actor_id: usize) -> bool {
+        match Battler::from_actor(self.database, actor_id) {
+            Some(battler) => {
+                self.members.push(battler);
+                true
+            },
+            None => false,
+        }
+    }
+
+    /// Hands the members over to a battle
+    pub fn take_members(&mut self) -> Vec<Battler<'a>> {
+        ::std::mem::replace(&mut self.members, Vec::new())
+    }
+
+    /// Takes the members back after a battle
+    pub fn set_members(&mut self, members: Vec<Battler<'a>>) {
+        self.members = members;
+    }
+
+    /// Hands the members over to a battle against a troop, with the items they can use in it
+    ///
+    /// The members stay in the party if the troop doesn't exist or has unknown enemies.
+    pub fn start_battle(&mut self, troop_id: usize) -> Option<Battle<'a>> {
+        // Battle::from_troop checks the same, but by then the members would be gone with it
+        let valid = match self.database.troops.get(&troop_id) {
+            Some(troop) => troop.members.iter().all(|member| self.database.enemies.contains_key(&member.enemy_id)),
+            None => false,
+        };
+
+        if !valid {
+            error!("Illegal troop id {}", troop_id);
+            return None;
+        }
+
+        let items = self.inventory.iter()
+            .filter_map(|(&item, &count)| match item {
+                ItemRef::Item(id) => Some((id, count)),
+                _ => None,
+            })
+            .collect();
+
+        let members = self.take_members();
+        let mut battle = match Battle::from_troop(self.database, members, troop_id) {
+            Some(battle) => battle,
+            None => return None,
+        };
+
+        battle.set_items(items);
+
+        return Some(battle);
+    }
+
+    /// Takes the members back from a finished battle, with the equipment they lost during it
+    ///
+    /// The items used in the battle are taken out of the inventory.
+    pub fn end_battle(&mut self, battle: Battle<'a>) {
+        for &item_id in battle.used_items().iter() {
+            if let Err(error) = self.consume_item(item_id) {
+                error!("Couldn't take item {} used in battle out of the inventory: {:?}", item_id, error);
+            }
+        }
+
+        let mut released = battle.released_equipment().clone();
+        self.apply_equip_delta(&mut released);
+
+        self.set_members(battle.into_party());
+
+        // Losing the battle-only states may have changed what the members can wear
+        for member in 0..self.members.len() {
+            self.release_unequippable(member);
+        }
+    }
+
+    /// Inflicts a state on a member, taking off whatever the state no longer allows
+    pub fn add_state(&mut self, member: usize, state_id: usize, random: &mut Random) -> bool {
+        let added = match self.members.get_mut(member) {
+            Some(battler) => battler.add_state(state_id, random),
+            None => return false,
+        };
+
+        self.release_unequippable(member);
+
+        return added;
+    }
+
+    /// Cures a member of a state, taking off whatever the member no longer allows
+    pub fn remove_state(&mut self, member: usize, state_id: usize) -> bool {
+        let removed = match self.members.get_mut(member) {
+            Some(battler) => battler.remove_state(state_id),
+            None => return false,
+        };
+
+        self.release_unequippable(member);
+
+        return removed;
+    }
+
+    /// Changes a member's class, taking off whatever the new class doesn't allow
+    pub fn change_class(&mut self, member: usize, class_id: usize) -> bool {
+        let changed = match self.members.get_mut(member) {
+            Some(battler) => battler.change_class(class_id),
+            None => return false,
+        };
+
+        self.release_unequippable(member);
+
+        return changed;
+    }
+
+    /// Puts a member's equipment that no longer fits back in the inventory
+    fn release_unequippable(&mut self, member: usize) {
+        let mut delta = match self.members.get_mut(member) {
+            Some(battler) => equipment::release_unequippable(battler),
+            None => return,
+        };
+
+        self.apply_equip_delta(&mut delta);
+    }
+
+    pub fn gold(&self) -> i32 {
+        self.gold
+    }
+
+    /// Adds (or with a negative amount, removes) gold, staying between 0 and the maximum
+    pub fn gain_gold(&mut self, amount: i32) {
+        let max_gold = self.database.system.max_gold;
+        self.gold = self.gold.saturating_add(amount).max(0).min(max_gold);
+    }
+
+    pub fn item_count(&self, item: ItemRef) -> u32 {
+        self.inventory.get(&item).cloned().unwrap_or(0)
+    }
+
+    pub fn has_item(&self, item: ItemRef) -> bool {
+        self.item_count(item) > 0
+    }
+
+    /// Adds items up to the stack limit, returning how many were actually added
+    pub fn gain_item(&mut self, item: ItemRef, count: u32) -> u32 {
+        if !self.database.has_item(item) {
+            error!("Illegal item {:?}", item);
+            return 0;
+        }
+
+        let current = self.item_count(item);
+        let added = count.min(self.database.system.max_stack.saturating_sub(current));
+
+        if added > 0 {
+            self.inventory.insert(item, current + added);
+        }
+
+        return added;
+    }
+
+    /// Removes items, failing without changing anything if there aren't enough
+    pub fn lose_item(&mut self, item: ItemRef, count: u32) -> Result<(), InventoryError> {
+        let current = self.item_count(item);
+
+        if current < count {
+            return Err(InventoryError::NotEnough);
+        }
+
+        if current == count {
+            self.inventory.remove(&item);
+        } else {
+            self.inventory.insert(item, current - count);
+        }
+
+        Ok(())
+    }
+
+    pub fn is_key_item(&self, item: ItemRef) -> bool {
+        match item {
+            ItemRef::Item(id) => self.database.items.get(&id).map(|i| i.item_type == ItemType::Key).unwrap_or(false),
+            _ => false,
+        }
+    }
+
+    /// Full price of an item, 0 for unknown items
+    pub fn price(&self, item: ItemRef) -> i32 {
+        match item {
+            ItemRef::Item(id) => self.database.items.get(&id).map(|i| i.price),
+            ItemRef::Weapon(id) => self.database.weapons.get(&id).map(|w| w.price),
+            ItemRef::Armor(id) => self.database.armors.get(&id).map(|a| a.price),
+        }.unwrap_or(0)
+    }
+
+    /// Sells items for half their price, returning the gold earned
+    pub fn sell(&mut self, item: ItemRef, count: u32) -> Result<i32, InventoryError> {
+        if self.is_key_item(item) {
+            return Err(InventoryError::KeyItem);
+        }
+
+        try!(self.lose_item(item, count));
+
+        let earned = self.price(item) / 2 * count as i32;
+        self.gain_gold(earned);
+
+        return Ok(earned);
+    }
+
+    /// Throws items away
+    pub fn discard(&mut self, item: ItemRef, count: u32) -> Result<(), InventoryError> {
+        if self.is_key_item(item) {
+            return Err(InventoryError::KeyItem);
+        }
+
+        self.lose_item(item, count)
+    }
+
+    /// Takes an item out of the inventory after it was used, if it's consumable
+    pub fn consume_item(&mut self, item_id: usize) -> Result<(), InventoryError> {
+        let consumable = match self.database.items.get(&item_id) {
+            Some(item) => item.consumable,
+            None => return Err(InventoryError::UnknownItem),
+        };
+
+        if !self.has_item(ItemRef::Item(item_id)) {
+            return Err(InventoryError::NotEnough);
+        }
+
+        if consumable {
+            try!(self.lose_item(ItemRef::Item(item_id), 1));
+        }
+
+        Ok(())
+    }
+
+    /// Moves items between the inventory and a member's equipment
+    ///
+    /// The new item must be in the inventory, and whatever it replaces goes back in.
+    pub fn change_equip(&mut self, member: usize, slot: EquipSlot, id: Option<usize>) -> Result<EquipDelta, InventoryError> {
+        if let Some(id) = id {
+            if !self.has_item(equipment::item_ref(slot, id)) {
+                return Err(InventoryError::NotEnough);
+            }
+        }
+
+        let mut delta = match self.members.get_mut(member) {
+            Some(battler) => try!(equipment::change_equip(battler, slot, id).map_err(InventoryError::Equip)),
+            None => return Err(InventoryError::UnknownItem),
+        };
+
+        self.apply_equip_delta(&mut delta);
+
+        return Ok(delta);
+    }
+
+    /// Applies the result of an equipment change to the inventory
+    ///
+    /// Items that don't fit because their stack is full are added to `delta.lost`.
+    pub fn apply_equip_delta(&mut self, delta: &mut EquipDelta) {
+        for &item in delta.from_inventory.iter() {
+            if self.lose_item(item, 1).is_err() {
+                error!("Equipped {:?} without having it in the inventory", item);
+            }
+        }
+
+        for &item in delta.to_inventory.iter() {
+            if self.gain_item(item, 1) == 0 {
+                error!("No room in the inventory for {:?}, it's lost", item);
+                delta.lost.push(item);
+            }
+        }
+    }
+
+    fn name(&self, item: ItemRef) -> &'a str {
+        let database = self.database;
+
+        match item {
+            ItemRef::Item(id) => database.items.get(&id).map(|i| &i.base.base.name[..]),
+            ItemRef::Weapon(id) => database.weapons.get(&id).map(|w| &w.base.name[..]),
+            ItemRef::Armor(id) => database.armors.get(&id).map(|a| &a.base.name[..]),
+        }.unwrap_or("")
+    }
+
+    fn in_category(&self, item: ItemRef, category: ItemCategory) -> bool {
+        match (category, item) {
+            (ItemCategory::All, _) => true,
+            (ItemCategory::Items, ItemRef::Item(_)) => !self.is_key_item(item),
+            (ItemCategory::KeyItems, ItemRef::Item(_)) => self.is_key_item(item),
+            (ItemCategory::Weapons, ItemRef::Weapon(_)) => true,
+            (ItemCategory::Armors, ItemRef::Armor(_)) => true,
+            _ => false,
+        }
+    }
+
+    /// Items of a category with their counts, sorted for display in a menu
+    pub fn list(&self, category: ItemCategory, sort: ItemSort) -> Vec<(ItemRef, u32)> {
+        let mut items: Vec<(ItemRef, u32)> = self.inventory.iter()
+            .filter(|&(&item, _)| self.in_category(item, category))
+            .map(|(&item, &count)| (item, count))
+            .collect();
+
+        // The inventory is already sorted by id, the other sorts fall back on it for ties
+        match sort {
+            ItemSort::Id => {},
+            ItemSort::Name => items.sort_by(|a, b| self.name(a.0).cmp(self.name(b.0))),
+            ItemSort::Count => items.sort_by(|a, b| b.1.cmp(&a.1)),
+        }
+
+        return items;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use battle::{Action, ActionKind, BattlerRef, Side};
+    use game_variables::GameVariables;
+    use random::SequenceRandom;
+
+    const POTION: ItemRef = ItemRef::Item(1);
+    const ETHER: ItemRef = ItemRef::Item(2);
+    const ANTIDOTE: ItemRef = ItemRef::Item(3);
+    const KEY: ItemRef = ItemRef::Item(5);
+    const SHORT_SWORD: ItemRef = ItemRef::Weapon(1);
+    const HAND_AXE: ItemRef = ItemRef::Weapon(2);
+    const STAR_ROBE: ItemRef = ItemRef::Armor(2);
+
+    fn database() -> Database {
+        Database::load("assets/data").unwrap()
+    }
+
+    #[test]
+    fn stacks_are_limited() {
+        let database = database();
+        let mut party = Party::new(&database);
+
+        assert_eq!(party.gain_item(POTION, 60), 60);
+        assert_eq!(party.gain_item(POTION, 60), 39);
+        assert_eq!(party.gain_item(POTION, 1), 0);
+        assert_eq!(party.item_count(POTION), 99);
+
+        assert_eq!(party.gain_item(ItemRef::Item(99), 1), 0);
+        assert!(!party.has_item(ItemRef::Item(99)));
+    }
+
+    #[test]
+    fn losing_items_needs_enough_of_them() {
+        let database = database();
+        let mut party = Party::new(&database);
+        party.gain_item(ETHER, 2);
+
+        assert_eq!(party.lose_item(ETHER, 3), Err(InventoryError::NotEnough));
+        assert_eq!(party.item_count(ETHER), 2);
+
+        assert_eq!(party.lose_item(ETHER, 2), Ok(()));
+        assert!(!party.has_item(ETHER));
+        assert_eq!(party.list(ItemCategory::All, ItemSort::Id), vec![]);
+    }
+
+    #[test]
+    fn gold_stays_between_zero_and_the_maximum() {
+        let mut database = database();
+        database.system.max_gold = 1000;
+        let mut party = Party::new(&database);
+
+        party.gain_gold(-5);
+        assert_eq!(party.gold(), 0);
+
+        party.gain_gold(800);
+        party.gain_gold(800);
+        assert_eq!(party.gold(), 1000);
+
+        party.gain_gold(::std::i32::MAX);
+        assert_eq!(party.gold(), 1000);
+
+        party.gain_gold(-300);
+        assert_eq!(party.gold(), 700);
+    }
+
+    #[test]
+    fn selling_pays_half_the_price() {
+        let database = database();
+        let mut party = Party::new(&database);
+        party.gain_item(POTION, 5);
+        party.gain_item(HAND_AXE, 1);
+
+        assert_eq!(party.sell(POTION, 3), Ok(75));
+        assert_eq!(party.sell(HAND_AXE, 1), Ok(90));
+        assert_eq!(party.gold(), 165);
+        assert_eq!(party.item_count(POTION), 2);
+
+        assert_eq!(party.sell(POTION, 3), Err(InventoryError::NotEnough));
+        assert_eq!(party.gold(), 165);
+    }
+
+    #[test]
+    fn key_items_cant_be_sold_or_discarded() {
+        let database = database();
+        let mut party = Party::new(&database);
+        party.gain_item(KEY, 1);
+
+        assert!(party.is_key_item(KEY));
+        assert!(!party.is_key_item(POTION));
+        assert_eq!(party.sell(KEY, 1), Err(InventoryError::KeyItem));
+        assert_eq!(party.discard(KEY, 1), Err(InventoryError::KeyItem));
+        assert_eq!(party.item_count(KEY), 1);
+        assert_eq!(party.gold(), 0);
+    }
+
+    #[test]
+    fn only_consumable_items_are_used_up() {
+        let database = database();
+        let mut party = Party::new(&database);
+        party.gain_item(POTION, 2);
+        party.gain_item(KEY, 1);
+
+        assert_eq!(party.consume_item(1), Ok(()));
+        assert_eq!(party.item_count(POTION), 1);
+
+        assert_eq!(party.consume_item(5), Ok(()));
+        assert_eq!(party.item_count(KEY), 1);
+
+        assert_eq!(party.consume_item(3), Err(InventoryError::NotEnough));
+        assert_eq!(party.consume_item(99), Err(InventoryError::UnknownItem));
+    }
+
+    #[test]
+    fn lists_items_by_category() {
+        let database = database();
+        let mut party = Party::new(&database);
+        party.gain_item(STAR_ROBE, 1);
+        party.gain_item(KEY, 1);
+        party.gain_item(SHORT_SWORD, 2);
+        party.gain_item(ETHER, 4);
+        party.gain_item(POTION, 3);
+
+        assert_eq!(party.list(ItemCategory::Items, ItemSort::Id), vec![(POTION, 3), (ETHER, 4)]);
+        assert_eq!(party.list(ItemCategory::KeyItems, ItemSort::Id), vec![(KEY, 1)]);
+        assert_eq!(party.list(ItemCategory::Weapons, ItemSort::Id), vec![(SHORT_SWORD, 2)]);
+        assert_eq!(party.list(ItemCategory::Armors, ItemSort::Id), vec![(STAR_ROBE, 1)]);
+        assert_eq!(party.list(ItemCategory::All, ItemSort::Id), vec![(POTION, 3), (ETHER, 4), (KEY, 1), (SHORT_SWORD, 2), (STAR_ROBE, 1)]);
+    }
+
+    #[test]
+    fn sorts_items_by_name_and_count() {
+        let database = database();
+        let mut party = Party::new(&database);
+        party.gain_item(POTION, 2);
+        party.gain_item(ETHER, 2);
+        party.gain_item(ANTIDOTE, 7);
+        party.gain_item(KEY, 1);
+
+        // Antidote, Ether, Observatory Key, Potion
+        assert_eq!(party.list(ItemCategory::All, ItemSort::Name), vec![(ANTIDOTE, 7), (ETHER, 2), (KEY, 1), (POTION, 2)]);
+
+        // Ties keep the id order
+        assert_eq!(party.list(ItemCategory::All, ItemSort::Count), vec![(ANTIDOTE, 7), (POTION, 2), (ETHER, 2), (KEY, 1)]);
+    }
+
+    #[test]
+    fn members_stay_when_the_battle_cant_start() {
+        let mut database = database();
+        database.troops.get_mut(&2).unwrap().members[1].enemy_id = 99;
+        let mut party = Party::new(&database);
+        party.add_actor(1);
+
+        assert!(party.start_battle(99).is_none());
+        assert_eq!(party.members().len(), 1);
+
+        assert!(party.start_battle(2).is_none());
+        assert_eq!(party.members().len(), 1);
+
+        let battle = party.start_battle(1).unwrap();
+        assert!(party.members().is_empty());
+
+        party.end_battle(battle);
+        assert_eq!(party.members().len(), 1);
+    }
+
+    #[test]
+    fn battles_use_up_items_of_the_inventory() {
+        let database = database();
+        let mut party = Party::new(&database);
+        party.add_actor(1);
+        party.gain_item(POTION, 2);
+        party.gain_item(KEY, 1);
+        party.gain_item(SHORT_SWORD, 1);
+
+        let mut battle = party.start_battle(1).unwrap();
+        assert_eq!(battle.item_count(1), 2);
+        assert_eq!(battle.item_count(5), 1);
+
+        let lilly = BattlerRef::new(Side::Party, 0);
+        battle.set_action(lilly, Action { kind: ActionKind::Item(1), target: Some(lilly) });
+        battle.process_turn(&GameVariables::new(), &mut SequenceRandom::new(vec![0.5]));
+
+        assert_eq!(battle.item_count(1), 1);
+        assert_eq!(battle.used_items(), &[1]);
+
+        // The inventory only changes once the battle is over
+        assert_eq!(party.item_count(POTION), 2);
+        party.end_battle(battle);
+        assert_eq!(party.item_count(POTION), 1);
+        assert_eq!(party.item_count(KEY), 1);
+    }
+}
@@ -0,0 +1,105 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Source of randomness for game logic
+///
+/// Everything that rolls dice takes a `&mut Random` so tests and replays can plug in
+/// their own deterministic implementation.
+pub trait Random {
+    /// Returns a number in the range [0, 1)
+    fn next_f32(&mut self) -> f32;
+
+    /// Returns true with the given probability (0.0 = never, 1.0 = always)
+    fn chance(&mut self, probability: f32) -> bool {
+        self.next_f32() < probability
+    }
+
+    /// Returns an integer in the range [0, max)
+    fn below(&mut self, max: usize) -> usize {
+        if max == 0 {
+            return 0;
+        }
+
+        let value = (self.next_f32() * max as f32) as usize;
+
+        // Guard against rounding up to max
+        if value >= max { max - 1 } else { value }
+    }
+
+    /// Returns an integer in the range [min, max]
+    fn range(&mut self, min: i32, max: i32) -> i32 {
+        if max <= min {
+            return min;
+        }
+
+        min + self.below((max - min + 1) as usize) as i32
+    }
+}
+
+/// A small and fast xorshift generator, good enough for gameplay
+pub struct XorShift {
+    state: u32,
+}
+
+impl XorShift {
+    pub fn new(seed: u32) -> XorShift {
+        XorShift {
+            // A zero state would only ever produce zeroes
+            state: if seed == 0 { 0x9E3779B9 } else { seed },
+        }
+    }
+
+    /// Returns a generator seeded from the system clock
+    pub fn from_time() -> XorShift {
+        let seed = match SystemTime::now().duration_since(UNIX_EPOCH) {
+            Ok(duration) => duration.subsec_nanos() ^ duration.as_secs() as u32,
+            Err(_) => 0,
+        };
+
+        XorShift::new(seed)
+    }
+
+    fn next_u32(&mut self) -> u32 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 17;
+        self.state ^= self.state << 5;
+        self.state
+    }
+}
+
+impl Random for XorShift {
+    fn next_f32(&mut self) -> f32 {
+        // Use the top 24 bits, which an f32 can represent exactly
+        (self.next_u32() >> 8) as f32 / (1 << 24) as f32
+    }
+}
+
+/// Returns a fixed sequence of values over and over, for tests that need to control every roll
+///
+/// E.g. `SequenceRandom::new(vec![0.0])` makes every chance succeed and `vec![0.99]` makes
+/// every chance below 0.99 fail.
+pub struct SequenceRandom {
+    values: Vec<f32>,
+    index: usize,
+}
+
+impl SequenceRandom {
+    pub fn new(values: Vec<f32>) -> SequenceRandom {
+        SequenceRandom {
+            values: values,
+            index: 0,
+        }
+    }
+}
+
+impl Random for SequenceRandom {
+    fn next_f32(&mut self) -> f32 {
+        if self.values.is_empty() {
+            return 0.0;
+        }
+
+        let value = self.values[self.index % self.values.len()];
+        self.index += 1;
+
+        value
+    }
+}
@@ -0,0 +1,292 @@
+use std::cmp::min;
+
+use sfml::graphics::{Transformable, Text, Drawable, Color, RenderTarget, RenderStates, RenderTexture, Texture, FloatRect};
+
+use window::{WindowState, Window};
+use iconset::Iconset;
+
+/// Draws the item at an index into the window contents, within the given area
+pub type ItemDrawer<'a> = Box<FnMut(usize, &mut RenderTexture, &FloatRect) + 'a>;
+
+// A window of items laid out in rows and columns, with a cursor to pick one
+// Only the rows that fit are drawn, the window scrolls to keep the cursor in view.
+pub struct SelectableWindow<'a> {
+    window: Window<'a>,
+    item_count: usize,
+    columns: usize,
+    item_height: f32,
+    index: usize, // The selected item
+    top_row: usize, // First row in view
+    wrap: bool, // Moving past the last row or column goes back to the first one
+    active: bool, // Takes input and shows the cursor
+    draw_item: Option<ItemDrawer<'a>>,
+}
+
+impl<'a> SelectableWindow<'a> {
+    pub fn new(skin: &'a Texture, x: f32, y: f32, width: f32, height: f32, columns: usize, item_height: f32) -> SelectableWindow<'a> {
+        SelectableWindow {
+            window: Window::new(skin, x, y, width, height),
+            item_count: 0,
+            columns: if columns < 1 { 1 } else { columns },
+            item_height: item_height,
+            index: 0,
+            top_row: 0,
+            wrap: true,
+            active: true,
+            draw_item: None,
+        }
+    }
+
+    /// Sets the number of items and how to draw each, then draws the ones in view
+    pub fn set_items(&mut self, item_count: usize, draw_item: ItemDrawer<'a>) {
+        self.item_count = item_count;
+        self.draw_item = Some(draw_item);
+
+        if self.index >= item_count {
+            self.index = if item_count > 0 { item_count - 1 } else { 0 };
+        }
+
+        self.top_row = 0;
+        self.ensure_visible();
+        self.refresh();
+    }
+
+    pub fn get_index(&self) -> usize {
+        self.index
+    }
+
+    pub fn set_index(&mut self, index: usize) {
+        if index < self.item_count {
+            self.select(index);
+        } else {
+            error!("Illegal item index {}, there are {} items", index, self.item_count);
+        }
+    }
+
+    pub fn item_count(&self) -> usize {
+        self.item_count
+    }
+
+    pub fn set_wrap(&mut self, wrap: bool) {
+        self.wrap = wrap;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    pub fn set_active(&mut self, active: bool) {
+        self.active = active;
+        self.update_cursor();
+    }
+
+    /// The window under the list, e.g. to open or close it
+    pub fn window(&mut self) -> &mut Window<'a> {
+        &mut self.window
+    }
+
+    pub fn set_state(&mut self, new_state: WindowState) {
+        self.window.set_state(new_state);
+    }
+
+    pub fn update(&mut self, delta_time: f32) {
+        self.window.update(delta_time);
+    }
+
+    fn row_count(&self) -> usize {
+        (self.item_count + self.columns - 1) / self.columns
+    }
+
+    /// Rows that fit in the window at once
+    fn visible_rows(&self) -> usize {
+        let rows = (self.window.get_content_size().y / self.item_height) as usize;
+        if rows < 1 { 1 } else { rows }
+    }
+
+    fn max_top_row(&self) -> usize {
+        self.row_count().saturating_sub(self.visible_rows())
+    }
+
+    /// Area of an item in the contents, relative to the rows in view
+    pub fn item_rect(&self, index: usize) -> FloatRect {
+        let width = self.window.get_content_size().x / self.columns as f32;
+        let row = index / self.columns;
+        let column = index % self.columns;
+
+        FloatRect::new(column as f32 * width, (row as f32 - self.top_row as f32) * self.item_height, width, self.item_height)
+    }
+
+    /// Whether the cursor can move, there is nothing to select in an empty or inactive list
+    fn can_move(&self) -> bool {
+        self.active && self.item_count > 0 && self.window.is_open()
+    }
+
+    pub fn move_down(&mut self) {
+        if !self.can_move() {
+            return;
+        }
+
+        if self.index + self.columns < self.item_count {
+            let index = self.index + self.columns;
+            self.select(index);
+        } else if self.wrap {
+            let index = self.index % self.columns;
+            self.select(index);
+        }
+    }
+
+    pub fn move_up(&mut self) {
+        if !self.can_move() {
+            return;
+        }
+
+        if self.index >= self.columns {
+            let index = self.index - self.columns;
+            self.select(index);
+        } else if self.wrap {
+            // The same column in the last row, or the row above it if the last one is short
+            let mut index = (self.row_count() - 1) * self.columns + self.index;
+            if index >= self.item_count {
+                index -= self.columns;
+            }
+
+            if index < self.item_count {
+                self.select(index);
+            }
+        }
+    }
+
+    pub fn move_right(&mut self) {
+        if !self.can_move() || self.columns < 2 {
+            return;
+        }
+
+        if self.index + 1 < self.item_count && (self.index + 1) % self.columns != 0 {
+            let index = self.index + 1;
+            self.select(index);
+        } else if self.wrap {
+            let index = (self.index + 1) % self.item_count;
+            self.select(index);
+        }
+    }
+
+    pub fn move_left(&mut self) {
+        if !self.can_move() || self.columns < 2 {
+            return;
+        }
+
+        if self.index % self.columns != 0 {
+            let index = self.index - 1;
+            self.select(index);
+        } else if self.wrap {
+            let index = (self.index + self.item_count - 1) % self.item_count;
+            self.select(index);
+        }
+    }
+
+    /// Moves a page down, scrolling the list with the cursor
+    pub fn page_down(&mut self) {
+        if !self.can_move() {
+            return;
+        }
+
+        let page = self.visible_rows();
+        self.top_row = min(self.top_row + page, self.max_top_row());
+        self.index = min(self.index + page * self.columns, self.item_count - 1);
+        self.ensure_visible();
+        self.refresh();
+    }
+
+    /// Moves a page up, scrolling the list with the cursor
+    pub fn page_up(&mut self) {
+        if !self.can_move() {
+            return;
+        }
+
+        let page = self.visible_rows();
+        self.top_row = self.top_row.saturating_sub(page);
+        self.index = self.index.saturating_sub(page * self.columns);
+        self.ensure_visible();
+        self.refresh();
+    }
+
+    fn select(&mut self, index: usize) {
+        self.index = index;
+
+        if self.ensure_visible() {
+            self.refresh();
+        } else {
+            self.update_cursor();
+        }
+    }
+
+    /// Scrolls so the selected item is in view, returns whether it scrolled
+    fn ensure_visible(&mut self) -> bool {
+        let row = self.index / self.columns;
+        let old_top_row = self.top_row;
+
+        if row < self.top_row {
+            self.top_row = row;
+        } else if row >= self.top_row + self.visible_rows() {
+            self.top_row = row + 1 - self.visible_rows();
+        }
+
+        return self.top_row != old_top_row;
+    }
+
+    fn update_cursor(&mut self) {
+        let cursor = if self.active && self.item_count > 0 { Some(self.item_rect(self.index)) } else { None };
+        let up = self.top_row > 0;
+        let down = self.top_row < self.max_top_row();
+
+        self.window.set_cursor_rect(cursor);
+        self.window.set_scroll_arrows(up, down);
+    }
+
+    /// Redraws the items in view
+    pub fn refresh(&mut self) {
+        self.window.get_contents().clear(&Color::new_rgba(0, 0, 0, 0));
+
+        let first = self.top_row * self.columns;
+        let last = min((self.top_row + self.visible_rows()) * self.columns, self.item_count);
+        let rects: Vec<FloatRect> = (first..last).map(|index| self.item_rect(index)).collect();
+
+        if let Some(ref mut draw_item) = self.draw_item {
+            for (index, rect) in (first..last).zip(rects.iter()) {
+                draw_item(index, self.window.get_contents(), rect);
+            }
+        }
+
+        self.update_cursor();
+    }
+}
+
+impl<'a> Drawable for SelectableWindow<'a> {
+    fn draw<RT: RenderTarget>(&self, target: &mut RT, rs: &mut RenderStates) {
+        target.draw_with_renderstates(&self.window, rs);
+    }
+}
+
+/// Draws an item as its icon, its name, and its count at the right if it has one
+///
+/// Meant to be called from an `ItemDrawer`, e.g. for an inventory list.
+pub fn draw_icon_item(contents: &mut RenderTexture, rect: &FloatRect, text: &mut Text, iconset: &Iconset, icon: usize, name: &str, count: Option<u32>) {
+    let icon_size = iconset.icon_size() as f32;
+
+    let mut icon_sprite = iconset.get(icon);
+    icon_sprite.set_position2f(rect.left, rect.top + (rect.height - icon_size) / 2.0);
+    contents.draw(&icon_sprite);
+
+    let text_top = rect.top + (rect.height - text.get_character_size() as f32) / 2.0;
+
+    text.set_string(name);
+    text.set_position2f(rect.left + icon_size + 4.0, text_top);
+    contents.draw(&*text);
+
+    if let Some(count) = count {
+        text.set_string(&format!("x{}", count));
+        let width = text.get_local_bounds().width;
+        text.set_position2f(rect.left + rect.width - width - 4.0, text_top);
+        contents.draw(&*text);
+    }
+}
@@ -0,0 +1,280 @@
+use battler::Battler;
+use database_items::base_item::*;
+use database_items::equip_item::EquipKind;
+use database_items::skill::{Skill, Cost};
+use database_items::usable_item::Occasion;
+
+/// Why a battler can't use a skill right now
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum Unusable {
+    UnknownSkill,
+    WrongOccasion,
+    SkillTypeMissing, // Only Common skills are usable without an AddSkillType feature
+    SkillTypeDisabled,
+    SkillDisabled,
+    WeaponRequired(WeaponType),
+    NotEnoughHp,
+    NotEnoughMp,
+    NotEnoughAp,
+    NoItemLeft, // An item the party ran out of during a battle
+}
+
+/// What using a skill costs a particular battler
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub struct SkillCost {
+    pub hp: i32,
+    pub mp: i32,
+    pub ap: i32,
+}
+
+/// Resolves a cost against the maximum value it is a percentage of
+fn resolve(cost: Cost, max: i32) -> i32 {
+    match cost {
+        Cost::Flat(amount) => amount,
+        Cost::Percentage(rate) => (max as f32 * rate) as i32,
+        Cost::None => 0,
+    }
+}
+
+/// The costs of a skill for a battler, with the MP cost scaled by its MP cost rate
+pub fn skill_cost(battler: &Battler, skill: &Skill) -> SkillCost {
+    let mp = resolve(skill.mp_cost, battler.mmp()) as f32 * battler.sparam(SpParameter::Mcr);
+
+    SkillCost {
+        hp: resolve(skill.hp_cost, battler.mhp()),
+        mp: mp as i32,
+        ap: resolve(skill.ap_cost, battler.map()),
+    }
+}
+
+fn has_weapon_type(battler: &Battler, weapon_type: WeaponType) -> bool {
+    battler.equipment().iter()
+        .filter_map(|(&slot, &id)| battler.database().equip_item(slot, id))
+        .any(|item| item.kind == EquipKind::Weapon(weapon_type))
+}
+
+/// Checks whether a battler can use a skill on the given occasion (Battle or Menu)
+pub fn can_use(battler: &Battler, skill_id: usize, occasion: Occasion) -> Result<(), Unusable> {
+    let skill = match battler.database().skills.get(&skill_id) {
+        Some(skill) => skill,
+        None => return Err(Unusable::UnknownSkill),
+    };
+
+    let occasion_met = match skill.base.occasion {
+        Occasion::Always => true,
+        Occasion::Never => false,
+        skill_occasion => skill_occasion == occasion,
+    };
+
+    if !occasion_met {
+        return Err(Unusable::WrongOccasion);
+    }
+
+    let features = battler.features();
+
+    if features.iter().any(|f| **f == Feature::DisableSkill(skill_id)) {
+        return Err(Unusable::SkillDisabled);
+    }
+
+    if features.iter().any(|f| **f == Feature::DisableSkillType(skill.skill_type)) {
+        return Err(Unusable::SkillTypeDisabled);
+    }
+
+    if skill.skill_type != SkillType::Common && !features.iter().any(|f| **f == Feature::AddSkillType(skill.skill_type)) {
+        return Err(Unusable::SkillTypeMissing);
+    }
+
+    // Enemies have no equipment, they use weapon skills without one
+    if let (Some(weapon_type), None) = (skill.required_weapon, battler.enemy_id()) {
+        if !has_weapon_type(battler, weapon_type) {
+            return Err(Unusable::WeaponRequired(weapon_type));
+        }
+    }
+
+    let cost = skill_cost(battler, skill);
+
+    // Paying HP may not kill the user
+    if cost.hp > 0 && battler.hp() <= cost.hp {
+        return Err(Unusable::NotEnoughHp);
+    }
+
+    if battler.mp() < cost.mp {
+        return Err(Unusable::NotEnoughMp);
+    }
+
+    if battler.ap() < cost.ap {
+        return Err(Unusable::NotEnoughAp);
+    }
+
+    Ok(())
+}
+
+/// Checks whether a skill can be used and takes its costs from the battler
+///
+/// Nothing is paid when an error is returned.
+pub fn pay_cost(battler: &mut Battler, skill_id: usize, occasion: Occasion) -> Result<SkillCost, Unusable> {
+    try!(can_use(battler, skill_id, occasion));
+
+    let cost = match battler.database().skills.get(&skill_id) {
+        Some(skill) => skill_cost(battler, skill),
+        None => return Err(Unusable::UnknownSkill),
+    };
+
+    let (hp, mp, ap) = (battler.hp(), battler.mp(), battler.ap());
+    battler.set_hp(hp - cost.hp);
+    battler.set_mp(mp - cost.mp);
+    battler.set_ap(ap - cost.ap);
+
+    return Ok(cost);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use database::Database;
+
+    // Lilly is an Astromancer with a Short Sword, 450 max HP, 80 max MP and 100 max AP, who
+    // can use Astromancy and Auramancy skills
+    const ATTACK: usize = 1;
+    const GUARD: usize = 2;
+    const STARFALL: usize = 3; // Astromancy, 12 MP
+    const VENOM_EDGE: usize = 4; // Needs a sword, 10% of max AP
+    const HEAL: usize = 5; // Auramancy, 5 MP
+
+    /// The demo database with extra features on Lilly's class
+    fn database(features: Vec<Feature>) -> Database {
+        let mut database = Database::load("assets/data").unwrap();
+        database.classes.get_mut(&1).unwrap().base.features.extend(features);
+
+        return database;
+    }
+
+    fn lilly<'a>(database: &'a Database) -> Battler<'a> {
+        Battler::from_actor(database, 1).unwrap()
+    }
+
+    fn cost(hp: i32, mp: i32, ap: i32) -> SkillCost {
+        SkillCost { hp: hp, mp: mp, ap: ap }
+    }
+
+    #[test]
+    fn flat_and_percentage_costs() {
+        let mut database = database(vec![]);
+        database.skills.get_mut(&ATTACK).unwrap().hp_cost = Cost::Percentage(0.15);
+        database.skills.get_mut(&GUARD).unwrap().mp_cost = Cost::Percentage(0.33);
+        let lilly = lilly(&database);
+
+        assert_eq!((lilly.mhp(), lilly.mmp(), lilly.map()), (450, 80, 100));
+        assert_eq!(skill_cost(&lilly, &database.skills[&STARFALL]), cost(0, 12, 0));
+        assert_eq!(skill_cost(&lilly, &database.skills[&VENOM_EDGE]), cost(0, 0, 10));
+
+        // Percentages of the maximum are rounded down: 67.5 HP and 26.4 MP
+        assert_eq!(skill_cost(&lilly, &database.skills[&ATTACK]), cost(67, 0, 0));
+        assert_eq!(skill_cost(&lilly, &database.skills[&GUARD]), cost(0, 26, 0));
+    }
+
+    #[test]
+    fn mp_cost_rate_scales_mp_costs() {
+        let mut database = database(vec![Feature::SpParameter(SpParameter::Mcr, 0.5)]);
+        database.skills.get_mut(&GUARD).unwrap().mp_cost = Cost::Percentage(0.25);
+        database.skills.get_mut(&VENOM_EDGE).unwrap().hp_cost = Cost::Flat(10);
+        let lilly = lilly(&database);
+
+        assert_eq!(skill_cost(&lilly, &database.skills[&STARFALL]), cost(0, 6, 0));
+        assert_eq!(skill_cost(&lilly, &database.skills[&HEAL]), cost(0, 2, 0)); // 2.5 rounded down
+        assert_eq!(skill_cost(&lilly, &database.skills[&GUARD]), cost(0, 10, 0));
+
+        // Only MP costs are scaled
+        assert_eq!(skill_cost(&lilly, &database.skills[&VENOM_EDGE]), cost(10, 0, 10));
+    }
+
+    #[test]
+    fn occasions() {
+        let mut database = database(vec![]);
+        database.skills.get_mut(&GUARD).unwrap().base.occasion = Occasion::Never;
+        let lilly = lilly(&database);
+
+        assert_eq!(can_use(&lilly, ATTACK, Occasion::Battle), Ok(()));
+        assert_eq!(can_use(&lilly, ATTACK, Occasion::Menu), Err(Unusable::WrongOccasion));
+        assert_eq!(can_use(&lilly, HEAL, Occasion::Battle), Ok(()));
+        assert_eq!(can_use(&lilly, HEAL, Occasion::Menu), Ok(()));
+        assert_eq!(can_use(&lilly, GUARD, Occasion::Battle), Err(Unusable::WrongOccasion));
+        assert_eq!(can_use(&lilly, 99, Occasion::Battle), Err(Unusable::UnknownSkill));
+    }
+
+    #[test]
+    fn skill_types_need_to_be_added() {
+        let mut database = database(vec![]);
+        database.classes.get_mut(&1).unwrap().base.features.retain(|f| *f != Feature::AddSkillType(SkillType::Auramancy));
+        let lilly = lilly(&database);
+
+        assert_eq!(can_use(&lilly, ATTACK, Occasion::Battle), Ok(()));
+        assert_eq!(can_use(&lilly, STARFALL, Occasion::Battle), Ok(()));
+        assert_eq!(can_use(&lilly, HEAL, Occasion::Battle), Err(Unusable::SkillTypeMissing));
+    }
+
+    #[test]
+    fn features_disable_skills_and_skill_types() {
+        let database = database(vec![Feature::DisableSkillType(SkillType::Astromancy), Feature::DisableSkill(ATTACK)]);
+        let lilly = lilly(&database);
+
+        assert_eq!(can_use(&lilly, STARFALL, Occasion::Battle), Err(Unusable::SkillTypeDisabled));
+        assert_eq!(can_use(&lilly, ATTACK, Occasion::Battle), Err(Unusable::SkillDisabled));
+        assert_eq!(can_use(&lilly, VENOM_EDGE, Occasion::Battle), Ok(()));
+    }
+
+    #[test]
+    fn weapon_skills_need_the_weapon() {
+        let database = database(vec![]);
+        let mut lilly = lilly(&database);
+
+        assert_eq!(can_use(&lilly, VENOM_EDGE, Occasion::Battle), Ok(()));
+
+        lilly.set_equipment(EquipSlot::RightHand, None);
+        assert_eq!(can_use(&lilly, VENOM_EDGE, Occasion::Battle), Err(Unusable::WeaponRequired(WeaponType::Sword)));
+
+        lilly.set_equipment(EquipSlot::RightHand, Some(2)); // Hand Axe
+        assert_eq!(can_use(&lilly, VENOM_EDGE, Occasion::Battle), Err(Unusable::WeaponRequired(WeaponType::Sword)));
+
+        // Enemies don't need one
+        let slime = Battler::from_enemy(&database, 1).unwrap();
+        assert_eq!(can_use(&slime, VENOM_EDGE, Occasion::Battle), Ok(()));
+    }
+
+    #[test]
+    fn costs_must_be_affordable() {
+        let mut database = database(vec![]);
+        database.skills.get_mut(&ATTACK).unwrap().hp_cost = Cost::Flat(100);
+        let mut lilly = lilly(&database);
+
+        // Paying HP may not kill
+        lilly.set_hp(100);
+        assert_eq!(can_use(&lilly, ATTACK, Occasion::Battle), Err(Unusable::NotEnoughHp));
+        lilly.set_hp(101);
+        assert_eq!(can_use(&lilly, ATTACK, Occasion::Battle), Ok(()));
+
+        lilly.set_mp(11);
+        assert_eq!(can_use(&lilly, STARFALL, Occasion::Battle), Err(Unusable::NotEnoughMp));
+        lilly.set_mp(12);
+        assert_eq!(can_use(&lilly, STARFALL, Occasion::Battle), Ok(()));
+
+        lilly.set_ap(9);
+        assert_eq!(can_use(&lilly, VENOM_EDGE, Occasion::Battle), Err(Unusable::NotEnoughAp));
+        lilly.set_ap(10);
+        assert_eq!(can_use(&lilly, VENOM_EDGE, Occasion::Battle), Ok(()));
+    }
+
+    #[test]
+    fn pay_cost_takes_resources() {
+        let database = database(vec![]);
+        let mut lilly = lilly(&database);
+
+        assert_eq!(pay_cost(&mut lilly, STARFALL, Occasion::Battle), Ok(cost(0, 12, 0)));
+        assert_eq!(pay_cost(&mut lilly, VENOM_EDGE, Occasion::Battle), Ok(cost(0, 0, 10)));
+        assert_eq!((lilly.hp(), lilly.mp(), lilly.ap()), (450, 68, 90));
+
+        // Nothing is paid for an unusable skill
+        assert_eq!(pay_cost(&mut lilly, STARFALL, Occasion::Menu), Err(Unusable::WrongOccasion));
+        assert_eq!((lilly.hp(), lilly.mp(), lilly.ap()), (450, 68, 90));
+    }
+}
@@ -0,0 +1,240 @@
+use battle::{BattlerRef, Side};
+use battler::Battler;
+use database_items::usable_item::{UsableItem, Scope, TargetSide};
+
+/// The battlers an item can be used on: both sides in battle, only the party in menus
+pub struct Battlefield<'b, 'a: 'b> {
+    pub party: &'b [Battler<'a>],
+    pub troop: &'b [Battler<'a>],
+    pub excluded: &'b [BattlerRef], // E.g. enemies that escaped
+}
+
+impl<'b, 'a> Battlefield<'b, 'a> {
+    /// Party members only, for using items and skills from the menu
+    pub fn menu(party: &'b [Battler<'a>]) -> Battlefield<'b, 'a> {
+        Battlefield {
+            party: party,
+            troop: &[],
+            excluded: &[],
+        }
+    }
+
+    pub fn battler(&self, battler: BattlerRef) -> Option<&'b Battler<'a>> {
+        match battler.side {
+            Side::Party => self.party.get(battler.index),
+            Side::Troop => self.troop.get(battler.index),
+        }
+    }
+
+    /// Whether an item can affect a battler: living ones, or fallen ones for revival items
+    pub fn is_valid_target(&self, target: BattlerRef, item: &UsableItem) -> bool {
+        match self.battler(target) {
+            Some(battler) => !self.excluded.contains(&target) && battler.is_dead() == item.for_dead,
+            None => false,
+        }
+    }
+
+    fn valid_targets(&self, side: Side, item: &UsableItem) -> Vec<BattlerRef> {
+        let count = match side {
+            Side::Party => self.party.len(),
+            Side::Troop => self.troop.len(),
+        };
+
+        (0..count)
+            .map(|index| BattlerRef::new(side, index))
+            .filter(|&target| self.is_valid_target(target, item))
+            .collect()
+    }
+
+    /// Every battler a user could pick (or hit all at once) with an item
+    pub fn candidates(&self, user: BattlerRef, item: &UsableItem) -> Vec<BattlerRef> {
+        match item.scope {
+            Scope::Noone => Vec::new(),
+            Scope::User => if self.is_valid_target(user, item) { vec![user] } else { Vec::new() },
+            Scope::Everyone => {
+                let mut everyone = self.valid_targets(Side::Party, item);
+                everyone.extend(self.valid_targets(Side::Troop, item));
+                everyone
+            },
+            _ => self.valid_targets(target_side(user.side, item), item),
+        }
+    }
+
+    /// Turns an item's scope and the user's choice into the battlers it affects
+    ///
+    /// `chosen` is None for a random target, and for OneOrWholeParty it toggles between one
+    /// battler and the whole party. A chosen target that is no longer valid (e.g. it died
+    /// earlier in the turn) is replaced by one from `pick_random`. A valid chosen target is
+    /// honored even on the "wrong" side, e.g. when confused.
+    pub fn resolve<F>(&self, user: BattlerRef, item: &UsableItem, chosen: Option<BattlerRef>, mut pick_random: F) -> Vec<BattlerRef>
+        where F: FnMut(&[BattlerRef]) -> Option<BattlerRef>
+    {
+        let candidates = self.candidates(user, item);
+
+        let mut one = || -> Vec<BattlerRef> {
+            match chosen {
+                Some(target) if self.is_valid_target(target, item) => vec![target],
+                _ => pick_random(&candidates).into_iter().collect(),
+            }
+        };
+
+        match item.scope {
+            Scope::Noone | Scope::User | Scope::WholeParty | Scope::Everyone => candidates.clone(),
+            Scope::OnePerson => one(),
+            Scope::OneOrWholeParty => if chosen.is_some() { one() } else { candidates.clone() },
+        }
+    }
+}
+
+/// The side an item is aimed at, from the user's point of view
+pub fn target_side(user_side: Side, item: &UsableItem) -> Side {
+    match item.target_side {
+        TargetSide::Opponents => user_side.opposite(),
+        TargetSide::Allies => user_side,
+    }
+}
+
+/// Whether the player picks a target for an item, instead of it hitting a fixed group
+pub fn needs_selection(item: &UsableItem) -> bool {
+    item.scope == Scope::OnePerson || item.scope == Scope::OneOrWholeParty
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use battle::{Battle, BattleEvent, Action};
+    use database::Database;
+    use game_variables::GameVariables;
+    use random::{Random, SequenceRandom};
+
+    const ATTACK: usize = 1; // One opponent
+    const GUARD: usize = 2; // The user
+    const POTION: usize = 1; // One ally
+    const BOMB: usize = 4; // Every opponent
+    const LIFE_BOTTLE: usize = 6; // One fallen ally
+
+    fn member(index: usize) -> BattlerRef {
+        BattlerRef::new(Side::Party, index)
+    }
+
+    fn enemy(index: usize) -> BattlerRef {
+        BattlerRef::new(Side::Troop, index)
+    }
+
+    /// Three slimes in the party, the middle one dead, against two hornets, the last one dead
+    fn battlers<'a>(database: &'a Database) -> (Vec<Battler<'a>>, Vec<Battler<'a>>) {
+        let mut party: Vec<Battler> = (0..3).map(|_| Battler::from_enemy(database, 1).unwrap()).collect();
+        let mut troop: Vec<Battler> = (0..2).map(|_| Battler::from_enemy(database, 2).unwrap()).collect();
+        party[1].set_hp(0);
+        troop[1].set_hp(0);
+
+        (party, troop)
+    }
+
+    fn skill(database: &Database, id: usize) -> &UsableItem {
+        &database.skills[&id].base
+    }
+
+    fn item(database: &Database, id: usize) -> &UsableItem {
+        &database.items[&id].base
+    }
+
+    #[test]
+    fn only_living_battlers_are_candidates() {
+        let database = Database::load("assets/data").unwrap();
+        let (party, troop) = battlers(&database);
+        let battlefield = Battlefield { party: &party, troop: &troop, excluded: &[] };
+
+        assert_eq!(battlefield.candidates(member(0), skill(&database, ATTACK)), vec![enemy(0)]);
+        assert_eq!(battlefield.candidates(enemy(0), skill(&database, ATTACK)), vec![member(0), member(2)]);
+        assert_eq!(battlefield.candidates(member(2), item(&database, POTION)), vec![member(0), member(2)]);
+        assert_eq!(battlefield.candidates(member(0), item(&database, BOMB)), vec![enemy(0)]);
+        assert_eq!(battlefield.candidates(member(0), skill(&database, GUARD)), vec![member(0)]);
+        assert_eq!(battlefield.candidates(member(1), skill(&database, GUARD)), vec![]);
+    }
+
+    #[test]
+    fn excluded_battlers_are_never_targets() {
+        let database = Database::load("assets/data").unwrap();
+        let (party, troop) = battlers(&database);
+        let escaped = [enemy(0)];
+        let battlefield = Battlefield { party: &party, troop: &troop, excluded: &escaped };
+
+        assert!(!battlefield.is_valid_target(enemy(0), skill(&database, ATTACK)));
+        assert_eq!(battlefield.candidates(member(0), item(&database, BOMB)), vec![]);
+        assert!(!battlefield.is_valid_target(enemy(5), skill(&database, ATTACK)));
+    }
+
+    #[test]
+    fn revival_items_target_the_fallen() {
+        let database = Database::load("assets/data").unwrap();
+        let (party, troop) = battlers(&database);
+        let battlefield = Battlefield { party: &party, troop: &troop, excluded: &[] };
+        let life_bottle = item(&database, LIFE_BOTTLE);
+
+        assert_eq!(battlefield.candidates(member(0), life_bottle), vec![member(1)]);
+        assert!(battlefield.is_valid_target(member(1), life_bottle));
+        assert!(!battlefield.is_valid_target(member(0), life_bottle));
+
+        // A living ally picked for a revival item is swapped for a fallen one
+        let mut random = SequenceRandom::new(vec![0.0]);
+        let targets = battlefield.resolve(member(0), life_bottle, Some(member(2)), |candidates| {
+            candidates.get(random.below(candidates.len())).cloned()
+        });
+        assert_eq!(targets, vec![member(1)]);
+    }
+
+    #[test]
+    fn invalid_choices_are_retargeted() {
+        let database = Database::load("assets/data").unwrap();
+        let (party, troop) = battlers(&database);
+        let battlefield = Battlefield { party: &party, troop: &troop, excluded: &[] };
+        let potion = item(&database, POTION);
+
+        let resolve = |chosen: Option<BattlerRef>, roll: f32| {
+            let mut random = SequenceRandom::new(vec![roll]);
+            battlefield.resolve(member(0), potion, chosen, |candidates| candidates.get(random.below(candidates.len())).cloned())
+        };
+
+        assert_eq!(resolve(Some(member(2)), 0.0), vec![member(2)]);
+        assert_eq!(resolve(Some(member(1)), 0.0), vec![member(0)]);
+        assert_eq!(resolve(Some(member(1)), 0.9), vec![member(2)]);
+        assert_eq!(resolve(None, 0.9), vec![member(2)]);
+
+        // Confusion may aim an item at the other side
+        assert_eq!(resolve(Some(enemy(0)), 0.0), vec![enemy(0)]);
+        assert_eq!(resolve(Some(enemy(1)), 0.0), vec![member(0)]);
+    }
+
+    #[test]
+    fn menus_only_see_the_party() {
+        let database = Database::load("assets/data").unwrap();
+        let (party, _) = battlers(&database);
+        let battlefield = Battlefield::menu(&party);
+
+        assert_eq!(battlefield.candidates(member(0), skill(&database, ATTACK)), vec![]);
+        assert_eq!(battlefield.candidates(member(0), item(&database, POTION)), vec![member(0), member(2)]);
+        assert_eq!(battlefield.candidates(member(0), item(&database, LIFE_BOTTLE)), vec![member(1)]);
+    }
+
+    #[test]
+    fn attacks_move_on_when_the_target_died_earlier_in_the_turn() {
+        let mut database = Database::load("assets/data").unwrap();
+        database.enemies.get_mut(&2).unwrap().base.features = Vec::new();
+
+        // Two hornets attack the first of two slimes, which the first hit kills
+        let party = vec![Battler::from_enemy(&database, 2).unwrap(), Battler::from_enemy(&database, 2).unwrap()];
+        let mut troop = vec![Battler::from_enemy(&database, 1).unwrap(), Battler::from_enemy(&database, 1).unwrap()];
+        troop[0].set_hp(10);
+
+        let mut battle = Battle::new(&database, party, troop);
+        battle.set_action(member(0), Action::attack(Some(enemy(0))));
+        battle.set_action(member(1), Action::attack(Some(enemy(0))));
+
+        let events = battle.process_turn(&GameVariables::new(), &mut SequenceRandom::new(vec![0.5]));
+
+        assert!(events.contains(&BattleEvent::Death(enemy(0))));
+        assert!(battle.battler(enemy(0)).is_dead());
+        assert!(battle.battler(enemy(1)).hp() < 180);
+    }
+}
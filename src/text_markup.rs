@@ -0,0 +1,392 @@
+use std::fmt;
+use std::iter::Peekable;
+use std::str::CharIndices;
+
+/// A range of bytes in the source text, `end` excluded
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    pub fn new(start: usize, end: usize) -> Span {
+        Span {
+            start: start,
+            end: end,
+        }
+    }
+}
+
+/// A number in a code, or the value of a variable for codes like "\n[\v[3]]"
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum CodeIndex {
+    Number(usize),
+    Variable(usize),
+}
+
+/// An escape code, see the letters in `parse`
+#[derive(PartialEq, Eq, Clone, Copy, Debug)]
+pub enum Code {
+    Icon(usize),
+    Color(u32), // Index of a color in the window skin
+    Size(u32),
+    Wait(u32), // In frames
+    ShortWait,
+    LongWait,
+    WaitForInput,
+    AutoClose,
+
+    // Replaced with text before the message is laid out
+    Variable(usize),
+    ActorName(CodeIndex),
+    PartyMemberName(CodeIndex), // From 1
+    Currency,
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum TokenKind {
+    Text(String), // Escaped backslashes are already turned into single ones
+    NewLine,
+    Code(Code),
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub enum MarkupErrorKind {
+    TrailingBackslash,
+    UnknownCode(char),
+    MissingArgument(char), // E.g. "\i" instead of "\i[3]"
+    UnclosedArgument(char), // E.g. "\i[3"
+    InvalidArgument(char, String),
+}
+
+#[derive(PartialEq, Clone, Debug)]
+pub struct MarkupError {
+    pub kind: MarkupErrorKind,
+    pub span: Span,
+}
+
+impl fmt::Display for MarkupError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        try!(write!(f, "at byte {}: ", self.span.start));
+
+        match self.kind {
+            MarkupErrorKind::TrailingBackslash => write!(f, "text ends with a lone backslash"),
+            MarkupErrorKind::UnknownCode(code) => write!(f, "unknown code \\{}", code),
+            MarkupErrorKind::MissingArgument(code) => write!(f, "code \\{} needs an argument in brackets", code),
+            MarkupErrorKind::UnclosedArgument(code) => write!(f, "argument of code \\{} is missing its ']'", code),
+            MarkupErrorKind::InvalidArgument(code, ref arg) => write!(f, "invalid argument '{}' for code \\{}", arg, code),
+        }
+    }
+}
+
+/// Splits a message into text runs, line breaks and codes
+///
+/// Codes are a backslash followed by a letter, with an argument in brackets for some:
+/// * `\i[n]` icon, `\c[n]` color, `\s[n]` text size
+/// * `\w[n]` wait n frames at 60 fps, `\.` wait a quarter second, `\|` wait a second
+/// * `\!` wait for the player, `\^` close without waiting for the player
+/// * `\v[n]` variable, `\n[n]` actor name, `\p[n]` party member name, `\g` currency unit
+/// * `\\` a backslash
+///
+/// Malformed codes are left out of the tokens and reported as errors, so the rest of the
+/// message can still be shown.
+pub fn parse(text: &str) -> (Vec<Token>, Vec<MarkupError>) {
+    let mut tokens = Vec::new();
+    let mut errors = Vec::new();
+    let mut chars = text.char_indices().peekable();
+
+    while let Some((start, character)) = chars.next() {
+        match character {
+            '\n' => tokens.push(Token {
+                kind: TokenKind::NewLine,
+                span: Span::new(start, start + 1),
+            }),
+
+            '\\' => match parse_code(text, start, &mut chars) {
+                Ok(Some(token)) => tokens.push(token),
+                Ok(None) => push_text(&mut tokens, '\\', Span::new(start, start + 2)),
+                Err(error) => errors.push(error),
+            },
+
+            _ => push_text(&mut tokens, character, Span::new(start, start + character.len_utf8())),
+        }
+    }
+
+    return (tokens, errors);
+}
+
+/// Adds a character to the text run before it, or starts a new run
+fn push_text(tokens: &mut Vec<Token>, character: char, span: Span) {
+    if let Some(&mut Token { kind: TokenKind::Text(ref mut text), span: ref mut run_span }) = tokens.last_mut() {
+        if run_span.end == span.start {
+            text.push(character);
+            run_span.end = span.end;
+            return;
+        }
+    }
+
+    tokens.push(Token {
+        kind: TokenKind::Text(character.to_string()),
+        span: span,
+    });
+}
+
+/// Parses the code after a backslash at `start`, returns None for an escaped backslash
+fn parse_code(text: &str, start: usize, chars: &mut Peekable<CharIndices>) -> Result<Option<Token>, MarkupError> {
+    let error = |kind: MarkupErrorKind, end: usize| MarkupError {
+        kind: kind,
+        span: Span::new(start, end),
+    };
+
+    let (letter_start, letter) = match chars.next() {
+        Some(next) => next,
+        None => return Err(error(MarkupErrorKind::TrailingBackslash, text.len())),
+    };
+
+    let letter_end = letter_start + letter.len_utf8();
+
+    let argless_code = match letter {
+        '\\' => return Ok(None),
+        '.' => Some(Code::ShortWait),
+        '|' => Some(Code::LongWait),
+        '!' => Some(Code::WaitForInput),
+        '^' => Some(Code::AutoClose),
+        'g' => Some(Code::Currency),
+        'i' | 'c' | 's' | 'w' | 'v' | 'n' | 'p' => None,
+        _ => return Err(error(MarkupErrorKind::UnknownCode(letter), letter_end)),
+    };
+
+    if let Some(code) = argless_code {
+        return Ok(Some(Token {
+            kind: TokenKind::Code(code),
+            span: Span::new(start, letter_end),
+        }));
+    }
+
+    if chars.peek().map(|&(_, c)| c) != Some('[') {
+        return Err(error(MarkupErrorKind::MissingArgument(letter), letter_end));
+    }
+
+    // Find the matching bracket, arguments may contain codes themselves. The brackets are only
+    // consumed if they're closed, otherwise the rest of the text is still shown as it is.
+    let mut scan = chars.clone();
+    let mut depth = 0;
+    let mut arg_end = None;
+
+    scan.next();
+
+    while let Some((position, c)) = scan.next() {
+        match c {
+            '[' => depth += 1,
+            ']' if depth == 0 => {
+                arg_end = Some(position);
+                break;
+            },
+            ']' => depth -= 1,
+            _ => { },
+        }
+    }
+
+    let arg_end = match arg_end {
+        Some(arg_end) => arg_end,
+        None => return Err(error(MarkupErrorKind::UnclosedArgument(letter), letter_end)),
+    };
+
+    *chars = scan;
+
+    let arg = &text[letter_end + 1..arg_end];
+    let span = Span::new(start, arg_end + 1);
+    let invalid = || MarkupError {
+        kind: MarkupErrorKind::InvalidArgument(letter, arg.to_string()),
+        span: span,
+    };
+
+    let code = match letter {
+        'i' => Code::Icon(try!(arg.parse().map_err(|_| invalid()))),
+        'c' => Code::Color(try!(arg.parse().map_err(|_| invalid()))),
+        's' => Code::Size(try!(arg.parse().map_err(|_| invalid()))),
+        'w' => Code::Wait(try!(arg.parse().map_err(|_| invalid()))),
+        'v' => Code::Variable(try!(arg.parse().map_err(|_| invalid()))),
+        'n' => Code::ActorName(try!(parse_index(arg).ok_or_else(invalid))),
+        _ => Code::PartyMemberName(try!(parse_index(arg).ok_or_else(invalid))),
+    };
+
+    Ok(Some(Token {
+        kind: TokenKind::Code(code),
+        span: span,
+    }))
+}
+
+/// Parses "3" or "\v[3]"
+fn parse_index(arg: &str) -> Option<CodeIndex> {
+    if arg.starts_with("\\v[") && arg.ends_with("]") {
+        arg[3..arg.len() - 1].parse().ok().map(CodeIndex::Variable)
+    } else {
+        arg.parse().ok().map(CodeIndex::Number)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn token(kind: TokenKind, start: usize, end: usize) -> Token {
+        Token {
+            kind: kind,
+            span: Span::new(start, end),
+        }
+    }
+
+    fn text(text: &str, start: usize, end: usize) -> Token {
+        token(TokenKind::Text(text.to_string()), start, end)
+    }
+
+    fn code(code: Code, start: usize, end: usize) -> Token {
+        token(TokenKind::Code(code), start, end)
+    }
+
+    fn error(kind: MarkupErrorKind, start: usize, end: usize) -> MarkupError {
+        MarkupError {
+            kind: kind,
+            span: Span::new(start, end),
+        }
+    }
+
+    /// Parses a single code that should take the whole text
+    fn single_code(markup: &str) -> Code {
+        let (tokens, errors) = parse(markup);
+        assert!(errors.is_empty(), "{:?}", errors);
+        assert_eq!(tokens.len(), 1);
+        assert_eq!(tokens[0].span, Span::new(0, markup.len()));
+
+        match tokens[0].kind {
+            TokenKind::Code(code) => code,
+            ref kind => panic!("{:?} is not a code", kind),
+        }
+    }
+
+    #[test]
+    fn text_runs_are_merged() {
+        assert_eq!(parse("Hello there"), (vec![text("Hello there", 0, 11)], vec![]));
+        assert_eq!(parse(""), (vec![], vec![]));
+    }
+
+    #[test]
+    fn new_lines_split_runs() {
+        assert_eq!(parse("ab\ncd"), (vec![
+            text("ab", 0, 2),
+            token(TokenKind::NewLine, 2, 3),
+            text("cd", 3, 5),
+        ], vec![]));
+    }
+
+    #[test]
+    fn escaped_backslash_joins_the_run() {
+        assert_eq!(parse("a\\\\b"), (vec![text("a\\b", 0, 4)], vec![]));
+        assert_eq!(parse("\\\\\\\\"), (vec![text("\\\\", 0, 4)], vec![]));
+    }
+
+    #[test]
+    fn codes_split_runs() {
+        assert_eq!(parse("a\\c[2]b\\!"), (vec![
+            text("a", 0, 1),
+            code(Code::Color(2), 1, 6),
+            text("b", 6, 7),
+            code(Code::WaitForInput, 7, 9),
+        ], vec![]));
+    }
+
+    #[test]
+    fn every_code() {
+        assert_eq!(single_code("\\i[12]"), Code::Icon(12));
+        assert_eq!(single_code("\\c[3]"), Code::Color(3));
+        assert_eq!(single_code("\\s[24]"), Code::Size(24));
+        assert_eq!(single_code("\\w[30]"), Code::Wait(30));
+        assert_eq!(single_code("\\."), Code::ShortWait);
+        assert_eq!(single_code("\\|"), Code::LongWait);
+        assert_eq!(single_code("\\!"), Code::WaitForInput);
+        assert_eq!(single_code("\\^"), Code::AutoClose);
+        assert_eq!(single_code("\\v[7]"), Code::Variable(7));
+        assert_eq!(single_code("\\n[1]"), Code::ActorName(CodeIndex::Number(1)));
+        assert_eq!(single_code("\\p[2]"), Code::PartyMemberName(CodeIndex::Number(2)));
+        assert_eq!(single_code("\\g"), Code::Currency);
+    }
+
+    #[test]
+    fn names_picked_by_variables() {
+        assert_eq!(single_code("\\n[\\v[3]]"), Code::ActorName(CodeIndex::Variable(3)));
+        assert_eq!(single_code("\\p[\\v[10]]"), Code::PartyMemberName(CodeIndex::Variable(10)));
+
+        assert_eq!(parse("\\n[\\v[3]]!"), (vec![
+            code(Code::ActorName(CodeIndex::Variable(3)), 0, 9),
+            text("!", 9, 10),
+        ], vec![]));
+    }
+
+    #[test]
+    fn trailing_backslash() {
+        assert_eq!(parse("ab\\"), (vec![text("ab", 0, 2)], vec![error(MarkupErrorKind::TrailingBackslash, 2, 3)]));
+    }
+
+    #[test]
+    fn unknown_code() {
+        assert_eq!(parse("a\\xb"), (
+            vec![text("a", 0, 1), text("b", 3, 4)],
+            vec![error(MarkupErrorKind::UnknownCode('x'), 1, 3)],
+        ));
+    }
+
+    #[test]
+    fn missing_argument() {
+        assert_eq!(parse("a\\ib"), (
+            vec![text("a", 0, 1), text("b", 3, 4)],
+            vec![error(MarkupErrorKind::MissingArgument('i'), 1, 3)],
+        ));
+    }
+
+    #[test]
+    fn unclosed_argument() {
+        // Only the code is dropped, the rest is shown as it is
+        assert_eq!(parse("a\\i[3"), (
+            vec![text("a", 0, 1), text("[3", 3, 5)],
+            vec![error(MarkupErrorKind::UnclosedArgument('i'), 1, 3)],
+        ));
+    }
+
+    #[test]
+    fn invalid_argument() {
+        assert_eq!(parse("a\\i[abc]b"), (
+            vec![text("a", 0, 1), text("b", 8, 9)],
+            vec![error(MarkupErrorKind::InvalidArgument('i', "abc".to_string()), 1, 8)],
+        ));
+
+        assert_eq!(parse("\\n[\\v[x]]").1, vec![error(MarkupErrorKind::InvalidArgument('n', "\\v[x]".to_string()), 0, 9)]);
+    }
+
+    #[test]
+    fn text_after_bad_codes_is_kept() {
+        let (tokens, errors) = parse("\\x one \\i two \\c[z] three \\v[1] four");
+
+        assert_eq!(tokens, vec![
+            text(" one ", 2, 7),
+            text(" two ", 9, 14),
+            text(" three ", 19, 26),
+            code(Code::Variable(1), 26, 31),
+            text(" four", 31, 36),
+        ]);
+        assert_eq!(errors.len(), 3);
+    }
+
+    #[test]
+    fn error_messages() {
+        let (_, errors) = parse("ab\\i[abc]");
+        assert_eq!(errors[0].to_string(), "at byte 2: invalid argument 'abc' for code \\i");
+    }
+}
@@ -1,42 +1,91 @@
-use std::str::FromStr;
-
-use sfml::graphics::{Transformable, Text, Drawable, Color, RenderTarget, RenderStates};
+use sfml::graphics::{Transformable, Text, Drawable, Color, RenderTarget, RenderStates, Texture, Font};
 
 use window::{WindowState, Window};
 use game_settings_manager::GameSettingsManager;
 use iconset::Iconset;
+use battler::Battler;
+use database::Database;
+use game_variables::GameVariables;
+use text_markup::{self, TokenKind, Code, CodeIndex};
 
 // A window designed to show text
-// Supports a few escape codes to show other things, or change the text properties
+// Supports the escape codes of `text_markup` to show other things, or change the text properties.
+// Codes that insert text (\v, \n, \p and \g) are replaced by `substitute` before the text is laid out.
+//
+// The text is split into tokens once when it's set, typing then just walks through them.
 pub struct TextWindow<'a> {
     window: Window<'a>,
     text: Text<'a>,
     current_text: String,
-    target_text: String,
-    current_character: usize,
+    tokens: Vec<TextToken>,
+    current_token: usize, // Index of the next token to type
     state: TextWindowState,
     current_pos_x: f32,
     current_pos_y: f32,
+    line_height: f32, // Height of the tallest character or icon on the current line
+    at_word_start: bool,
+    breaking_word: bool, // The current word is wider than the window, so it wraps anywhere
+    page_full: bool,
+    text_speed: f32, // Characters typed per second
+    typing_time: f32, // Seconds left over from the last update, towards the next character
+    fast_forward: bool, // Reveals the rest of the page at once
+    auto_close: bool, // Closes the window at the end of the text without waiting for input
     iconset: &'a Iconset,
+    skin: &'a Texture,
+    font: &'a Font,
+    default_font_size: u32, // Size codes only change the message, the name box keeps this one
+    face: Option<Face<'a>>,
+    name_box: Option<Window<'a>>, // Shows who is speaking, above the message
+}
+
+/// Which side of the message a face is shown on
+#[derive(PartialEq, Clone, Copy, Debug)]
+pub enum FaceSide {
+    Left,
+    Right,
+}
+
+/// A face portrait for a message, cut from a sheet of square faces like icons from an iconset
+#[derive(Clone, Copy)]
+pub struct Face<'a> {
+    pub sheet: &'a Iconset,
+    pub index: usize,
+    pub side: FaceSide,
 }
 
 #[derive(PartialEq, Clone, Copy)]
 pub enum TextWindowState {
     Typing,
-    Paused(u32), // Pause duration left in frames
+    Paused(f32), // Pause duration left in seconds
+    WaitingForInput, // Until the player confirms, at the end of a page, of the text or at a \! code
     Done,
 }
 
 
+/// A piece of text to type: a single character, a line break or an escape code
 #[derive(PartialEq, Clone, Copy, Debug)]
-pub enum TextCode {
-    Icon(usize),
-    ChangeColor(Color),
-    ChangeSize(u32),
-    Pause(u32),
-    Unknown,
+pub enum TextToken {
+    Character(char),
+    NewLine,
+    Code(Code),
 }
 
+/// Pause durations of the \. and \| codes, in seconds
+const SHORT_WAIT: f32 = 0.25;
+const LONG_WAIT: f32 = 1.0;
+
+/// Length of a frame in \w codes, which count frames of a 60 fps game
+const WAIT_FRAME_DURATION: f32 = 1.0 / 60.0;
+
+/// Default characters typed per second
+const DEFAULT_TEXT_SPEED: f32 = 60.0;
+
+/// Space between a face and the text
+const FACE_MARGIN: f32 = 8.0;
+
+/// Space around the name in the name box
+const NAME_BOX_PADDING: f32 = 12.0;
+
 impl<'a> TextWindow<'a> {
 
     pub fn new(settings: &'a GameSettingsManager, text: &str, x: f32, y: f32, width: f32, height: f32) -> TextWindow<'a> {
@@ -44,199 +93,634 @@ impl<'a> TextWindow<'a> {
             window: Window::new(&settings.window_skin, x, y, width, height),
             text: Text::new_init("", &settings.font, settings.default_font_size).unwrap(),
             current_text: "".to_string(),
-            target_text: text.to_string(),
-            current_character: 0,
+            tokens: tokenize(text),
+            current_token: 0,
             state: TextWindowState::Typing,
             current_pos_x: 0.0,
             current_pos_y: 0.0,
+            line_height: settings.default_font_size as f32,
+            at_word_start: true,
+            breaking_word: false,
+            page_full: false,
+            text_speed: DEFAULT_TEXT_SPEED,
+            typing_time: 0.0,
+            fast_forward: false,
+            auto_close: false,
             iconset: &settings.iconset,
+            skin: &settings.window_skin,
+            font: &settings.font,
+            default_font_size: settings.default_font_size,
+            face: None,
+            name_box: None,
         }
     }
 
-    pub fn update(&mut self) {
+    /// Types the text and animates the window, `delta_time` seconds after the last update
+    pub fn update(&mut self, delta_time: f32) {
         // If window is open, do updates
         if self.window.get_state() == WindowState::Static && self.window.is_open() {
-            match self.state {
-                // Draw the next character
-                TextWindowState::Typing => {
-                    self.current_character += 1;
-                    let character = self.target_text.chars().take(self.current_character).last().unwrap();
-
-                    if character == '\\' {
-                        let code = self.parse_escape_code();
-                        self.execute_code(code);
-                    } else if character == '\n' {
-                        self.current_pos_y += self.text.get_character_size() as f32;
-                        self.current_pos_x = 0.0;
-                    } else {
-                        // Draw the next character if there's no escape sequence
-                        self.current_text.push(character);
-
-                        self.text.set_string(&character.to_string());
-
-                        self.text.set_position2f(self.current_pos_x, self.current_pos_y);
-                        self.current_pos_x += self.text.get_local_bounds().width;
-
-                        self.window.get_contents().draw(&self.text);
-                    }
-
-
-                    if self.current_character == self.target_text.len() {
-                        self.state = TextWindowState::Done;
-                    }
-                },
-
-                // Pause for a frame and decrease duration
-                TextWindowState::Paused(duration) => {
-                    if duration <= 0 {
-                        self.state = TextWindowState::Typing;
-                    } else {
-                        self.state = TextWindowState::Paused(duration - 1);
-                    }
-                },
+            // Draw the rest of the page in one go, skipping pauses
+            while self.fast_forward && self.state != TextWindowState::WaitingForInput && self.state != TextWindowState::Done {
+                self.type_next();
+            }
+
+            self.fast_forward = false;
+
+            // Type as many characters as the time allows, pauses use up time too
+            let mut time = self.typing_time + delta_time;
+
+            loop {
+                match self.state {
+                    TextWindowState::Typing => {
+                        // Only visible characters take time, codes and line breaks run right away
+                        let visible = match self.tokens.get(self.current_token) {
+                            Some(&TextToken::Character(character)) => !character.is_whitespace(),
+                            _ => false,
+                        };
+
+                        if visible {
+                            let character_time = 1.0 / self.text_speed;
+                            if time < character_time {
+                                break;
+                            }
 
-                // Do nothing
-                TextWindowState::Done => { },
+                            time -= character_time;
+                        }
+
+                        self.type_next();
+                    },
+
+                    TextWindowState::Paused(duration) => {
+                        if time < duration {
+                            self.state = TextWindowState::Paused(duration - time);
+                            time = 0.0;
+                            break;
+                        }
+
+                        time -= duration;
+                        self.state = TextWindowState::Typing;
+                    },
+
+                    // Nothing to type until the player confirms, or at all
+                    TextWindowState::WaitingForInput | TextWindowState::Done => {
+                        time = 0.0;
+                        break;
+                    },
+                }
             }
+
+            self.typing_time = time;
         }
 
-        self.window.update();
+        self.window.update(delta_time);
+
+        if let Some(ref mut name_box) = self.name_box {
+            name_box.update(delta_time);
+        }
     }
 
-    fn parse_escape_code(&mut self) -> TextCode {
-        let mut code = self.target_text.chars().skip(self.current_character).take_while(|&c| c != ']' );
-        let length = self.target_text.chars().skip(self.current_character).take_while(|&c| c != ']' ).collect::<String>().len() + 1;
+    /// Done once the text was shown and the player confirmed it, e.g. to show choices after it
+    pub fn get_state(&self) -> TextWindowState {
+        self.state
+    }
 
-        // Add the length of the whole escape sequence to to the current character to skip them from being printed
-        self.current_character += length;
+    /// Handles the player pressing the confirm key
+    ///
+    /// While typing this shows the rest of the page at once, while waiting it shows the next
+    /// page or closes the window at the end of the text.
+    pub fn confirm(&mut self) {
+        match self.state {
+            TextWindowState::Typing | TextWindowState::Paused(_) => self.fast_forward = true,
+
+            TextWindowState::WaitingForInput => {
+                self.window.set_pause(false);
+
+                if self.page_full {
+                    self.page_full = false;
+                    self.clear_page();
+                }
+
+                if self.current_token >= self.tokens.len() {
+                    self.finish();
+                } else {
+                    self.state = TextWindowState::Typing;
+                }
+            },
 
-        let code_type = code.next().unwrap();
+            TextWindowState::Done => { },
+        }
+    }
 
-        let code_args = code.skip(1).collect::<String>();
-        let code_args = code_args.split(',').collect::<Vec<_>>();
+    fn wait_for_input(&mut self) {
+        self.state = TextWindowState::WaitingForInput;
+        self.window.set_pause(true);
+    }
 
-        let mut result = TextCode::Unknown;
+    fn finish(&mut self) {
+        self.state = TextWindowState::Done;
+        self.set_state(WindowState::Closing);
+    }
 
-        match code_type {
-            // Draw icon
-            'i' => {
-                    let arg = usize::from_str(code_args[0]);
+    /// Closes the window, or waits for the player first unless the text had a \^ code
+    fn end_of_text(&mut self) {
+        if self.auto_close {
+            self.finish();
+        } else {
+            self.wait_for_input();
+        }
+    }
 
-                    if arg.is_err() {
-                        error!("Invalid number string {} in Icon text code, defaulting to 0", code_args[0]);
-                        result = TextCode::Icon(0);
-                    } else {
-                        result = TextCode::Icon(arg.unwrap());
-                    }
-                },
+    fn type_next(&mut self) {
+        // Pauses are skipped when fast forwarding
+        if let TextWindowState::Paused(_) = self.state {
+            self.state = TextWindowState::Typing;
+        }
 
-            // Change text color
-            'c' => {
-                    let arg = u32::from_str(code_args[0]);
+        if self.current_token >= self.tokens.len() {
+            self.end_of_text();
+            return;
+        }
 
-                    if arg.is_err() {
-                        error!("Invalid number string {} in Color text code, defaulting to 0", code_args[0]);
-                        result = TextCode::ChangeColor(self.window.get_color(0));
-                    } else {
-                        result = TextCode::ChangeColor(self.window.get_color(arg.unwrap()));
-                    }
-                },
+        let token = self.tokens[self.current_token];
+        self.current_token += 1;
 
-            // Change text size
-            's' => {
-                    let arg = u32::from_str(code_args[0]);
+        let content_width = self.text_width();
 
-                    if arg.is_err() {
-                        error!("Invalid number string {} in Size text code, defaulting to 18", code_args[0]);
-                        result = TextCode::ChangeSize(18);
-                    } else {
-                        result = TextCode::ChangeSize(arg.unwrap());
-                    }
-                },
+        // Move a whole word to the next line if it would run past the right edge
+        if self.at_word_start && token != TextToken::Character(' ') && token != TextToken::NewLine {
+            self.at_word_start = false;
 
-            // Pause
-            'p' => {
-                    let arg = u32::from_str(code_args[0]);
-
-                    if arg.is_err() {
-                        error!("Invalid number string {} in Pause text code, defaulting to 0", code_args[0]);
-                        result = TextCode::Pause(0);
-                    } else {
-                        result = TextCode::Pause(arg.unwrap());
-                    }
-                },
-
-            // Invalid code
-            _ => result = TextCode::Unknown,
-        };
+            let start = self.current_token - 1;
+            let width = self.measure_word(start);
+            self.breaking_word = width > content_width;
+
+            if self.current_pos_x > 0.0 && self.current_pos_x + width > content_width {
+                if !self.new_line() {
+                    // Start the word over on the next page
+                    self.current_token -= 1;
+                    self.at_word_start = true;
+                    return;
+                }
+            }
+        }
+
+        // Words that can't fit on any line (e.g. Japanese text without spaces) wrap between characters
+        if let TextToken::Character(character) = token {
+            if self.breaking_word && self.current_pos_x > 0.0 && self.current_pos_x + self.character_width(character) > content_width {
+                if !self.new_line() {
+                    self.current_token -= 1;
+                    return;
+                }
+            }
+        }
+
+        match token {
+            TextToken::Code(code) => self.execute_code(code),
+
+            TextToken::NewLine => {
+                self.at_word_start = true;
+                self.new_line();
+            },
+
+            TextToken::Character(character) => {
+                if character == ' ' {
+                    self.at_word_start = true;
+                }
+
+                self.draw_character(character);
+            },
+        }
+
+        if self.current_token >= self.tokens.len() && self.state != TextWindowState::WaitingForInput {
+            self.end_of_text();
+        }
+    }
+
+    /// Moves to the start of the next line
+    ///
+    /// Returns false if the line doesn't fit in the window, the window then waits for input.
+    fn new_line(&mut self) -> bool {
+        let next_pos_y = self.current_pos_y + self.line_height;
+
+        self.current_pos_x = 0.0;
+        self.line_height = self.text.get_character_size() as f32;
+
+        if next_pos_y + self.line_height > self.window.get_content_size().y {
+            self.page_full = true;
+            self.wait_for_input();
+            return false;
+        }
+
+        self.current_pos_y = next_pos_y;
+
+        return true;
+    }
+
+    fn draw_character(&mut self, character: char) {
+        self.current_text.push(character);
+
+        let width = self.character_width(character);
+        self.text.set_position2f(self.text_left() + self.current_pos_x, self.current_pos_y);
+        self.current_pos_x += width;
+        self.line_height = self.line_height.max(self.text.get_character_size() as f32);
+
+        self.window.get_contents().draw(&self.text);
+    }
 
-        return result;
+    /// Width of a character at the current text size, also leaves it as the text's string
+    fn character_width(&mut self, character: char) -> f32 {
+        self.text.set_string(&character.to_string());
+        self.text.get_local_bounds().width
     }
 
-    fn execute_code(&mut self, code: TextCode) {
+    /// Width of the word starting at the given token, counting icons and size changes in it
+    fn measure_word(&mut self, start: usize) -> f32 {
+        let original_size = self.text.get_character_size();
+        let mut width = 0.0;
+
+        for index in start..self.tokens.len() {
+            match self.tokens[index] {
+                TextToken::Character(' ') | TextToken::NewLine => break,
+                TextToken::Character(character) => width += self.character_width(character),
+                TextToken::Code(Code::Icon(_)) => width += self.iconset.icon_size() as f32,
+                TextToken::Code(Code::Size(size)) => self.text.set_character_size(size),
+                TextToken::Code(_) => { },
+            }
+        }
+
+        self.text.set_character_size(original_size);
+
+        return width;
+    }
+
+    fn execute_code(&mut self, code: Code) {
         match code {
             // Draw icon
-            TextCode::Icon(id) => {
+            Code::Icon(id) => {
                 let mut icon = self.iconset.get(id);
-                icon.set_position2f(self.current_pos_x, self.current_pos_y);
+                icon.set_position2f(self.text_left() + self.current_pos_x, self.current_pos_y);
                 self.window.get_contents().draw(&icon);
 
                 self.current_pos_x += icon.get_local_bounds().width;
+                self.line_height = self.line_height.max(icon.get_local_bounds().height);
 
                 info!("Drawing icon #{:?}", id);
             },
 
             // Change text color
-            TextCode::ChangeColor(color) => {
+            Code::Color(id) => {
+                let color = self.window.get_color(id);
                 self.text.set_color(&color);
                 info!("Changing color to {:?}", color);
             },
 
             // Change text size
-            TextCode::ChangeSize(size) => {
+            Code::Size(size) => {
                 self.text.set_character_size(size);
                 info!("Changing size to {:?}", size);
             },
 
             // Pause
-            TextCode::Pause(duration) => {
-                self.state = TextWindowState::Paused(duration);
+            Code::Wait(frames) => self.pause(frames as f32 * WAIT_FRAME_DURATION),
+            Code::ShortWait => self.pause(SHORT_WAIT),
+            Code::LongWait => self.pause(LONG_WAIT),
+
+            // Wait for the player
+            Code::WaitForInput => {
+                self.wait_for_input();
+
+                info!("Waiting for input");
+            },
+
+            // Close at the end without waiting for the player
+            Code::AutoClose => {
+                self.auto_close = true;
+            },
 
-                info!("Pausing for {:?}", duration);
+            // Left over when the text wasn't substituted
+            Code::Variable(_) | Code::ActorName(_) | Code::PartyMemberName(_) | Code::Currency => {
+                error!("Text code {:?} wasn't substituted", code);
             },
+        }
+    }
+
+    /// Where lines start in the contents, after a face on the left
+    fn text_left(&self) -> f32 {
+        match self.face {
+            Some(Face { sheet, side: FaceSide::Left, .. }) => sheet.icon_size() as f32 + FACE_MARGIN,
+            _ => 0.0,
+        }
+    }
+
+    /// Width lines can take up, leaving room for a face
+    fn text_width(&self) -> f32 {
+        let content_width = self.window.get_content_size().x;
+
+        match self.face {
+            Some(Face { sheet, .. }) => content_width - sheet.icon_size() as f32 - FACE_MARGIN,
+            None => content_width,
+        }
+    }
+
+    /// Clears the contents for a new page, keeping the face
+    fn clear_page(&mut self) {
+        self.window.get_contents().clear(&Color::new_rgba(0, 0, 0, 0));
+        self.current_pos_x = 0.0;
+        self.current_pos_y = 0.0;
 
-            // Do nothing
-            TextCode::Unknown => { },
+        if let Some(face) = self.face {
+            let mut sprite = face.sheet.get(face.index);
+            let x = match face.side {
+                FaceSide::Left => 0.0,
+                FaceSide::Right => self.window.get_content_size().x - face.sheet.icon_size() as f32,
+            };
+
+            sprite.set_position2f(x, 0.0);
+            self.window.get_contents().draw(&sprite);
         }
     }
 
+    fn pause(&mut self, duration: f32) {
+        self.state = TextWindowState::Paused(duration);
+
+        info!("Pausing for {:?}", duration);
+    }
+
     // Clear the textbox
     pub fn reset(&mut self) {
         self.state = TextWindowState::Typing;
         self.current_text = "".to_string();
         self.text.set_string(&self.current_text);
-        self.current_character = 0;
-        self.current_pos_x = 0.0;
-        self.current_pos_y = 0.0;
-        self.window.get_contents().clear(&Color::new_rgba(0, 0, 0, 0));
+        self.current_token = 0;
+        self.line_height = self.text.get_character_size() as f32;
+        self.at_word_start = true;
+        self.breaking_word = false;
+        self.page_full = false;
+        self.typing_time = 0.0;
+        self.fast_forward = false;
+        self.auto_close = false;
+        self.window.set_pause(false);
+        self.clear_page();
     }
 
     pub fn change_text(&mut self, new_text: &str) {
         self.reset();
-        self.target_text = new_text.to_string();
+        self.tokens = tokenize(new_text);
+    }
+
+    /// The window under the text, e.g. to change its transition
+    pub fn window(&mut self) -> &mut Window<'a> {
+        &mut self.window
+    }
+
+    /// Sets how many characters are typed per second
+    pub fn set_text_speed(&mut self, text_speed: f32) {
+        if text_speed > 0.0 {
+            self.text_speed = text_speed;
+        } else {
+            error!("Illegal text speed {}, must be above 0", text_speed);
+        }
+    }
+
+    /// Sets the face shown next to the following messages, None to show none
+    pub fn set_face(&mut self, face: Option<Face<'a>>) {
+        self.face = face;
+        self.reset();
+    }
+
+    /// Sets the name shown in a box above the following messages, None to hide the box
+    pub fn set_speaker(&mut self, name: Option<&str>) {
+        self.name_box = name.map(|name| {
+            let mut text = Text::new_init(name, self.font, self.default_font_size).unwrap();
+            let bounds = text.get_local_bounds();
+            let width = bounds.width + NAME_BOX_PADDING * 2.0;
+            let height = self.default_font_size as f32 + NAME_BOX_PADDING * 2.0;
+            let position = self.window.get_position();
+
+            let mut name_box = Window::new(self.skin, position.x, position.y - height, width, height);
+            // Opens along with the message window, unless that one is closed
+            let state = self.window.get_state();
+            if state == WindowState::Closing || (state == WindowState::Static && !self.window.is_open()) {
+                name_box.set_state(WindowState::Closing);
+            }
+
+            let content_size = name_box.get_content_size();
+            text.set_position2f((content_size.x - bounds.width) / 2.0, (content_size.y - self.default_font_size as f32) / 2.0);
+            name_box.get_contents().draw(&text);
+
+            name_box
+        });
     }
 
     /// Change the state (Opening, Static, Closing) of the window
     pub fn set_state(&mut self, new_state: WindowState) {
         self.window.set_state(new_state);
-    }
-
-    //
 
+        if let Some(ref mut name_box) = self.name_box {
+            name_box.set_state(new_state);
+        }
+    }
 }
 
 impl<'a> Drawable for TextWindow<'a> {
     fn draw<RT: RenderTarget>(&self, target: &mut RT, rs: &mut RenderStates) {
         target.draw_with_renderstates(&self.window, rs);
+
+        if let Some(ref name_box) = self.name_box {
+            target.draw_with_renderstates(name_box, rs);
+        }
+    }
+}
+
+/// Splits text into characters, line breaks and escape codes
+///
+/// Malformed codes are logged and left out, the rest of the text is still shown.
+pub fn tokenize(text: &str) -> Vec<TextToken> {
+    let (markup, errors) = text_markup::parse(text);
+
+    for error in errors {
+        error!("Invalid text markup {}", error);
+    }
+
+    let mut tokens = Vec::new();
+
+    for token in markup {
+        match token.kind {
+            TokenKind::Text(run) => tokens.extend(run.chars().map(TextToken::Character)),
+            TokenKind::NewLine => tokens.push(TextToken::NewLine),
+            TokenKind::Code(code) => tokens.push(TextToken::Code(code)),
+        }
+    }
+
+    return tokens;
+}
+
+/// What the text substitution codes refer to
+pub struct TextContext<'b, 'a: 'b> {
+    pub database: &'a Database,
+    pub variables: &'b GameVariables,
+    pub party: &'b [Battler<'a>],
+}
+
+/// Replaces the codes that insert text (\v, \n, \p and \g) with that text
+///
+/// A variable can pick a name, e.g. "\n[\v[3]]". Everything else, including escaped
+/// backslashes and malformed codes, is kept as it is for the window to draw. Backslashes in
+/// the inserted text are escaped, so it's drawn as it is.
+pub fn substitute(text: &str, context: &TextContext) -> String {
+    let (tokens, _) = text_markup::parse(text);
+    let mut result = String::new();
+    let mut copied = 0; // End of the source text already in the result
+
+    for token in tokens {
+        // Malformed codes aren't tokens, keep them as they were
+        result.push_str(&text[copied..token.span.start]);
+        copied = token.span.end;
+
+        let replacement = match token.kind {
+            TokenKind::Code(Code::Variable(id)) => Some(context.variables.get(id).to_string()),
+            TokenKind::Code(Code::ActorName(index)) => {
+                let id = code_index(index, context);
+                context.database.actors.get(&id).map(|actor| actor.base.name.clone())
+            },
+            TokenKind::Code(Code::PartyMemberName(index)) => match code_index(index, context) {
+                0 => None,
+                index => context.party.get(index - 1).map(|battler| battler.name.clone()),
+            },
+            TokenKind::Code(Code::Currency) => Some(context.database.system.currency_unit.clone()),
+            _ => {
+                result.push_str(&text[token.span.start..token.span.end]);
+                continue;
+            },
+        };
+
+        match replacement {
+            Some(replacement) => result.push_str(&replacement.replace("\\", "\\\\")),
+            None => error!("Nothing to insert for text code {}", &text[token.span.start..token.span.end]),
+        }
+    }
+
+    result.push_str(&text[copied..]);
+
+    return result;
+}
+
+fn code_index(index: CodeIndex, context: &TextContext) -> usize {
+    match index {
+        CodeIndex::Number(number) => number,
+        CodeIndex::Variable(id) => {
+            let value = context.variables.get(id);
+            if value < 0 { 0 } else { value as usize }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use text_markup::{self, Span};
+
+    /// Tokens of a text without codes, one per character
+    fn characters(text: &str) -> Vec<TextToken> {
+        text.chars().map(TextToken::Character).collect()
+    }
+
+    #[test]
+    fn tokenize_multibyte_text() {
+        let text = "Café ☕ ありがとう 🎉!";
+        assert_eq!(tokenize(text), characters(text));
+    }
+
+    #[test]
+    fn tokenize_codes_between_multibyte_text() {
+        let text = "é\\c[2]日本\\.🐉\n\\i[5]ñ";
+
+        let mut expected = characters("é");
+        expected.push(TextToken::Code(Code::Color(2)));
+        expected.extend(characters("日本"));
+        expected.push(TextToken::Code(Code::ShortWait));
+        expected.push(TextToken::Character('🐉'));
+        expected.push(TextToken::NewLine);
+        expected.push(TextToken::Code(Code::Icon(5)));
+        expected.push(TextToken::Character('ñ'));
+
+        assert_eq!(tokenize(text), expected);
+    }
+
+    #[test]
+    fn markup_spans_fall_on_character_boundaries() {
+        let text = "é\\c[2]日本\\.🐉\n\\i[5]ñ";
+        let (tokens, errors) = text_markup::parse(text);
+
+        assert!(errors.is_empty());
+
+        let spans: Vec<&str> = tokens.iter().map(|token| &text[token.span.start..token.span.end]).collect();
+        assert_eq!(spans, vec!["é", "\\c[2]", "日本", "\\.", "🐉", "\n", "\\i[5]", "ñ"]);
+
+        // The tokens cover the whole text, one after the other
+        assert_eq!(tokens[0].span.start, 0);
+        assert_eq!(tokens.last().unwrap().span.end, text.len());
+        for pair in tokens.windows(2) {
+            assert_eq!(pair[0].span.end, pair[1].span.start);
+        }
+
+        assert_eq!(tokens[1].span, Span::new(2, 7));
+        assert_eq!(tokens[2].span, Span::new(7, 13));
+    }
+
+    #[test]
+    fn tokenize_skips_bad_codes_in_multibyte_text() {
+        assert_eq!(tokenize("日\\x本"), characters("日本"));
+        assert_eq!(tokenize("🎉\\i[ü]🎉"), characters("🎉🎉"));
+
+        // Only the code is dropped when its argument isn't closed
+        assert_eq!(tokenize("ö\\i[3ö"), characters("ö[3ö"));
+        assert_eq!(tokenize("ö\\"), characters("ö"));
+    }
+
+    #[test]
+    fn substitute_around_multibyte_text() {
+        let mut database = Database::load("assets/data").unwrap();
+        database.system.currency_unit = "€".to_string();
+        database.actors.get_mut(&1).unwrap().base.name = "Zoë".to_string();
+
+        let mut variables = GameVariables::new();
+        variables.set(3, 1);
+
+        let mut member = Battler::from_actor(&database, 1).unwrap();
+        member.name = "ゆき".to_string();
+        let party = vec![member];
+
+        let context = TextContext {
+            database: &database,
+            variables: &variables,
+            party: &party,
+        };
+
+        assert_eq!(substitute("「\\n[1]」は\\v[3]\\gを持つ🎉", &context), "「Zoë」は1€を持つ🎉");
+        assert_eq!(substitute("ñ\\n[\\v[3]]ñ\\p[1]ñ", &context), "ñZoëñゆきñ");
+
+        // Other codes, escaped backslashes and bad codes are left for the window
+        assert_eq!(substitute("日\\c[2]\\\\本\\x\\g", &context), "日\\c[2]\\\\本\\x€");
+    }
+
+    #[test]
+    fn substitute_escapes_backslashes_in_names() {
+        let database = Database::load("assets/data").unwrap();
+        let variables = GameVariables::new();
+
+        let mut member = Battler::from_actor(&database, 1).unwrap();
+        member.name = "ü\\c[2]".to_string();
+        let party = vec![member];
+
+        let context = TextContext {
+            database: &database,
+            variables: &variables,
+            party: &party,
+        };
+
+        let text = substitute("\\p[1]!", &context);
+        assert_eq!(text, "ü\\\\c[2]!");
+        assert_eq!(tokenize(&text), characters("ü\\c[2]!"));
     }
 }
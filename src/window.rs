@@ -1,7 +1,15 @@
-use sfml::graphics::{Drawable, Transformable, Sprite, RenderTexture, Texture, Color, RenderTarget, IntRect, RenderStates};
+use sfml::graphics::{Drawable, Transformable, Sprite, RenderTexture, Texture, Color, RenderTarget, IntRect, FloatRect, RenderStates};
 use sfml::system::vector2::Vector2f;
 
-const CONTENT_PADDING: u32 = 5;
+use animation::{self, Easing, Transition, Edge, Tween};
+
+pub const CONTENT_PADDING: u32 = 5;
+
+/// Default time to open or close a window, in seconds
+const DEFAULT_OPEN_DURATION: f32 = 0.1;
+
+/// Time each frame of the pause arrow is shown, in seconds
+const PAUSE_FRAME_DURATION: f32 = 0.125;
 
 #[derive(PartialEq, Clone, Copy)]
 pub enum WindowState {
@@ -18,10 +26,22 @@ pub struct Window<'a> {
     position: Vector2f,
     content_position: Vector2f,
     visible: bool,
-    window_opacity: f32,
+    window_opacity: f32, // From 0.0 to 1.0
     content_opacity: f32,
-    openness: f32,
+    window_fade: Option<Tween>, // Animates the opacity towards a new value
+    content_fade: Option<Tween>,
+    tone: Color, // Tints the background of the skin
+    openness: f32, // Linear progress of opening, from 0.0 to 1.0
     state: WindowState,
+    open_duration: f32, // In seconds
+    close_duration: f32,
+    easing: Easing,
+    transition: Transition,
+    pause: bool, // Shows the "press to continue" arrow
+    pause_time: f32, // Seconds the arrow has been shown for, to animate it
+    cursor: Option<FloatRect>, // In contents coordinates
+    scroll_up: bool, // Shows arrows telling there is more above or below the contents
+    scroll_down: bool,
 }
 
 
@@ -38,8 +58,20 @@ impl<'a> Window<'a> {
             visible: true,
             window_opacity: 1.0,
             content_opacity: 1.0,
+            window_fade: None,
+            content_fade: None,
+            tone: Color::new_rgba(255, 255, 255, 255),
             openness: 0.0,
             state: WindowState::Opening,
+            open_duration: DEFAULT_OPEN_DURATION,
+            close_duration: DEFAULT_OPEN_DURATION,
+            easing: Easing::Linear,
+            transition: Transition::Expand,
+            pause: false,
+            pause_time: 0.0,
+            cursor: None,
+            scroll_up: false,
+            scroll_down: false,
         };
 
         window.produce_window();
@@ -69,6 +101,7 @@ impl<'a> Window<'a> {
         background.set_texture_rect(&IntRect::new(0, 0, 64, 64));
         background.set_position2f(CONTENT_PADDING as f32 / 2.0, CONTENT_PADDING as f32 / 2.0);
         background.scale2f((self.size.x - CONTENT_PADDING as f32) / 64.0, (self.size.y - CONTENT_PADDING as f32) / 64.0);
+        background.set_color(&self.tone);
 
         let mut top_edge = Sprite::new_with_texture(self.skin).unwrap();
         top_edge.set_texture_rect(&IntRect::new(80, 0, 32, 16));
@@ -90,6 +123,7 @@ impl<'a> Window<'a> {
         right_edge.set_position2f(self.size.x - 16.0, 16.0);
         right_edge.scale2f(1.0, (self.size.y - 32.0) / 32.0);
 
+        self.window.clear(&Color::new_rgba(0, 0, 0, 0));
         self.window.draw(&background);
 
         self.window.draw(&top_left_corner);
@@ -103,17 +137,75 @@ impl<'a> Window<'a> {
         self.window.draw(&right_edge);
     }
 
+    /// Draws the cursor, stretching the skin's cursor but keeping its 8 pixel border sharp
+    fn draw_cursor<RT: RenderTarget>(&self, target: &mut RT, rs: &mut RenderStates, rect: &FloatRect, opacity: u8) {
+        let left = self.position.x + self.content_position.x + rect.left;
+        let top = self.position.y + self.content_position.y + rect.top;
+        let inner_width = (rect.width - 16.0).max(0.0);
+        let inner_height = (rect.height - 16.0).max(0.0);
+
+        // Columns and rows of the cursor's 3x3 slices: skin offset, size in the skin, drawn position and size
+        let columns = [(0, 8, 0.0, 8.0), (8, 16, 8.0, inner_width), (24, 8, 8.0 + inner_width, 8.0)];
+        let rows = [(0, 8, 0.0, 8.0), (8, 16, 8.0, inner_height), (24, 8, 8.0 + inner_height, 8.0)];
+
+        for &(skin_x, skin_width, x, width) in &columns {
+            for &(skin_y, skin_height, y, height) in &rows {
+                let mut slice = Sprite::new_with_texture(self.skin).unwrap();
+                slice.set_texture_rect(&IntRect::new(64 + skin_x, 64 + skin_y, skin_width, skin_height));
+                slice.set_position2f(left + x, top + y);
+                slice.set_scale2f(width / skin_width as f32, height / skin_height as f32);
+                slice.set_color(&Color::new_rgba(255, 255, 255, opacity));
+
+                target.draw_with_renderstates(&slice, rs);
+            }
+        }
+    }
+
+    pub fn get_position(&self) -> Vector2f {
+        self.position
+    }
+
+    pub fn get_size(&self) -> Vector2f {
+        self.size
+    }
+
+    /// Size of the area text and other contents can be drawn in
+    pub fn get_content_size(&self) -> Vector2f {
+        Vector2f::new(self.size.x - CONTENT_PADDING as f32 * 2.0, self.size.y - CONTENT_PADDING as f32 * 2.0)
+    }
+
     /// Returns a reference to the RenderTexture of the contents of the window
     pub fn get_contents(&mut self) -> &mut RenderTexture {
         &mut self.contents
     }
 
-    /// Update the graphic of the window
-    pub fn update(&mut self) {
+    /// Update the graphic of the window, `delta_time` seconds after the last update
+    pub fn update(&mut self, delta_time: f32) {
+        if self.pause {
+            self.pause_time += delta_time;
+        }
+
+        // Animate fades
+        if let Some(ref mut fade) = self.window_fade {
+            self.window_opacity = fade.update(delta_time);
+        }
+
+        if let Some(ref mut fade) = self.content_fade {
+            self.content_opacity = fade.update(delta_time);
+        }
+
+        if self.window_fade.map_or(false, |fade| fade.is_done()) {
+            self.window_fade = None;
+        }
+
+        if self.content_fade.map_or(false, |fade| fade.is_done()) {
+            self.content_fade = None;
+        }
+
         // Animate opening
         if self.state == WindowState::Opening {
             if self.openness < 1.0 {
-                self.openness += 0.2;
+                self.openness += animation::step(delta_time, self.open_duration);
             }
 
             if self.openness >= 1.0 {
@@ -125,7 +217,7 @@ impl<'a> Window<'a> {
         // Animate closing
         if self.state == WindowState::Closing {
             if self.openness > 0.0 {
-                self.openness -= 0.2;
+                self.openness -= animation::step(delta_time, self.close_duration);
             }
 
             if self.openness <= 0.0 {
@@ -140,11 +232,91 @@ impl<'a> Window<'a> {
         self.state = new_state;
     }
 
-    /// Visibility
+    /// Sets how long opening and closing take, in seconds
+    pub fn set_durations(&mut self, open_duration: f32, close_duration: f32) {
+        self.open_duration = open_duration;
+        self.close_duration = close_duration;
+    }
+
+    pub fn set_easing(&mut self, easing: Easing) {
+        self.easing = easing;
+    }
+
+    pub fn set_transition(&mut self, transition: Transition) {
+        self.transition = transition;
+    }
+
+    /// Hidden windows aren't drawn, but still update
     pub fn set_visible(&mut self, visible: bool) {
         self.visible = visible;
     }
 
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    /// Opacity of the frame and background, from 0.0 to 1.0
+    pub fn get_window_opacity(&self) -> f32 {
+        self.window_opacity
+    }
+
+    pub fn set_window_opacity(&mut self, opacity: f32) {
+        self.window_opacity = opacity.max(0.0).min(1.0);
+        self.window_fade = None;
+    }
+
+    /// Opacity of the contents, from 0.0 to 1.0
+    pub fn get_content_opacity(&self) -> f32 {
+        self.content_opacity
+    }
+
+    pub fn set_content_opacity(&mut self, opacity: f32) {
+        self.content_opacity = opacity.max(0.0).min(1.0);
+        self.content_fade = None;
+    }
+
+    /// Fades the frame and background to an opacity over `duration` seconds, with the window's easing
+    pub fn fade_window_opacity(&mut self, opacity: f32, duration: f32) {
+        self.window_fade = Some(Tween::new(self.window_opacity, opacity.max(0.0).min(1.0), duration, self.easing));
+    }
+
+    /// Fades the contents to an opacity over `duration` seconds, with the window's easing
+    pub fn fade_content_opacity(&mut self, opacity: f32, duration: f32) {
+        self.content_fade = Some(Tween::new(self.content_opacity, opacity.max(0.0).min(1.0), duration, self.easing));
+    }
+
+    pub fn get_tone(&self) -> Color {
+        self.tone
+    }
+
+    /// Tints the background of the skin, e.g. to give windows a different color with the same skin
+    ///
+    /// The background's colors are multiplied by the tone, white keeps them as they are.
+    pub fn set_tone(&mut self, tone: Color) {
+        self.tone = tone;
+        self.produce_window();
+    }
+
+    /// Shows or hides the "press to continue" arrow at the bottom of the window
+    pub fn set_pause(&mut self, pause: bool) {
+        if pause && !self.pause {
+            self.pause_time = 0.0;
+        }
+
+        self.pause = pause;
+    }
+
+    /// Shows the skin's cursor around an area of the contents, or hides it with None
+    pub fn set_cursor_rect(&mut self, rect: Option<FloatRect>) {
+        self.cursor = rect;
+    }
+
+    /// Shows the scroll arrows at the top and bottom edges
+    pub fn set_scroll_arrows(&mut self, up: bool, down: bool) {
+        self.scroll_up = up;
+        self.scroll_down = down;
+    }
+
     pub fn get_state(&self) -> WindowState {
         self.state
     }
@@ -173,29 +345,88 @@ impl<'a> Window<'a> {
 
 impl<'a> Drawable for Window<'a> {
     fn draw<RT: RenderTarget>(&self, target: &mut RT, rs: &mut RenderStates) {
+        if !self.visible {
+            return;
+        }
+
         // Finalize drawings on the textures
         self.window.display();
         self.contents.display();
 
-        let window_opacity = self.window_opacity * 255.0;
-        let content_opacity = self.content_opacity * 255.0;
+        // Scale, opacity and offset of the opening or closing transition
+        let openness = self.easing.apply(self.openness);
+        let (scale, fade, offset) = match self.transition {
+            Transition::Expand => (openness, 1.0, Vector2f::new(0.0, 0.0)),
+            Transition::Fade => (1.0, openness, Vector2f::new(0.0, 0.0)),
+            Transition::Slide(edge) => {
+                let distance = 1.0 - openness;
+                let offset = match edge {
+                    Edge::Left => Vector2f::new(-self.size.x * distance, 0.0),
+                    Edge::Right => Vector2f::new(self.size.x * distance, 0.0),
+                    Edge::Top => Vector2f::new(0.0, -self.size.y * distance),
+                    Edge::Bottom => Vector2f::new(0.0, self.size.y * distance),
+                };
+
+                (1.0, openness, offset)
+            },
+        };
+
+        let window_opacity = self.window_opacity * fade * 255.0;
+        let content_opacity = self.content_opacity * fade * 255.0;
 
         let window_texture = &self.window.get_texture().unwrap();
         let mut window_sprite = Sprite::new_with_texture(&window_texture).unwrap();
         window_sprite.set_origin2f(self.size.x / 2.0, self.size.y / 2.0);
-        window_sprite.set_position2f(self.size.x / 2.0 + self.position.x, self.size.y / 2.0 + self.position.y);
-        window_sprite.set_scale2f(1.0, self.openness);
+        window_sprite.set_position2f(self.size.x / 2.0 + self.position.x + offset.x, self.size.y / 2.0 + self.position.y + offset.y);
+        window_sprite.set_scale2f(1.0, scale);
         window_sprite.set_color(&Color::new_rgba(255, 255, 255, window_opacity as u8));
 
         let content_texture = &self.contents.get_texture().unwrap();
         let mut content_sprite = Sprite::new_with_texture(&content_texture).unwrap();
         content_sprite.set_origin2f(self.size.x / 2.0, self.size.y / 2.0);
-        content_sprite.set_position2f(self.size.x / 2.0 + self.content_position.x + self.position.x,
-                                      self.size.y / 2.0 + self.content_position.y + self.position.y);
-        content_sprite.set_scale2f(1.0, self.openness);
+        content_sprite.set_position2f(self.size.x / 2.0 + self.content_position.x + self.position.x + offset.x,
+                                      self.size.y / 2.0 + self.content_position.y + self.position.y + offset.y);
+        content_sprite.set_scale2f(1.0, scale);
         content_sprite.set_color(&Color::new_rgba(255, 255, 255, content_opacity as u8));
 
         target.draw_with_renderstates(&window_sprite, rs);
+
+        // The cursor goes under the contents, once the window is fully open
+        if let Some(ref rect) = self.cursor {
+            if self.openness >= 1.0 {
+                self.draw_cursor(target, rs, rect, content_opacity as u8);
+            }
+        }
+
         target.draw_with_renderstates(&content_sprite, rs);
+
+        // Scroll arrows sit in the middle of the top and bottom edges
+        if self.openness >= 1.0 {
+            let arrows = [(self.scroll_up, IntRect::new(88, 16, 16, 8), 0.0), (self.scroll_down, IntRect::new(88, 40, 16, 8), self.size.y - 8.0)];
+
+            for &(shown, ref skin_rect, y) in &arrows {
+                if shown {
+                    let mut arrow = Sprite::new_with_texture(self.skin).unwrap();
+                    arrow.set_texture_rect(skin_rect);
+                    arrow.set_position2f(self.position.x + (self.size.x - 16.0) / 2.0, self.position.y + y);
+                    arrow.set_color(&Color::new_rgba(255, 255, 255, content_opacity as u8));
+
+                    target.draw_with_renderstates(&arrow, rs);
+                }
+            }
+        }
+
+        // The arrow sits in the middle of the bottom edge, once the window is fully open
+        if self.pause && self.openness >= 1.0 {
+            // It cycles through the 4 frames of the skin, in a 2x2 grid
+            let frame = (self.pause_time / PAUSE_FRAME_DURATION) as i32 % 4;
+
+            let mut pause_sprite = Sprite::new_with_texture(self.skin).unwrap();
+            pause_sprite.set_texture_rect(&IntRect::new(96 + frame % 2 * 16, 64 + frame / 2 * 16, 16, 16));
+            pause_sprite.set_position2f(self.position.x + (self.size.x - 16.0) / 2.0, self.position.y + self.size.y - 16.0);
+            pause_sprite.set_color(&Color::new_rgba(255, 255, 255, content_opacity as u8));
+
+            target.draw_with_renderstates(&pause_sprite, rs);
+        }
     }
 }